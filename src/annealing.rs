@@ -0,0 +1,257 @@
+//! A simulated annealing optimizer, as an alternative to the greedy assignment in `LayoutCreator`.
+//!
+//! Starting from a layout, it repeatedly swaps two random keys. Swaps which lower the cost are always kept, and swaps which raise it
+//! are kept with a probability that shrinks as the temperature cools. This lets it climb out of the local minima that the greedy
+//! assignment gets stuck in, and it can take the interactions between keys into account since it scores whole layouts. Costs
+//! which can be updated a swap at a time (see `SwapCost`) only have to go over the keys which move.
+
+use crate::{evaluator::SwapScorer, keyboard::KeyboardLayout, random::Random};
+
+/// A cost which can tell how much swapping two keys changes it, without working it out for the whole layout again.
+pub trait SwapCost {
+    /// The cost of the layout with the swaps so far.
+    fn cost(&self) -> f64;
+    /// How much swapping the keys at the two positions would change the cost.
+    fn swap_delta(&self, first: (usize, usize), second: (usize, usize)) -> f64;
+    fn swap(&mut self, first: (usize, usize), second: (usize, usize));
+}
+
+/// Works out the cost of the whole layout for every swap, for costs which can't be updated a swap at a time.
+pub struct FullCost<F> {
+    layout: KeyboardLayout,
+    cost_of: F,
+    cost: f64,
+}
+
+impl<F: Fn(&KeyboardLayout) -> f64> FullCost<F> {
+    pub fn new(layout: KeyboardLayout, cost_of: F) -> Self {
+        Self {
+            cost: cost_of(&layout),
+            layout,
+            cost_of,
+        }
+    }
+}
+
+impl<F: Fn(&KeyboardLayout) -> f64> SwapCost for FullCost<F> {
+    fn cost(&self) -> f64 {
+        self.cost
+    }
+
+    fn swap_delta(&self, first: (usize, usize), second: (usize, usize)) -> f64 {
+        let mut layout = self.layout.clone();
+        layout.swap(first, second);
+        (self.cost_of)(&layout) - self.cost
+    }
+
+    fn swap(&mut self, first: (usize, usize), second: (usize, usize)) {
+        self.layout.swap(first, second);
+        self.cost = (self.cost_of)(&self.layout);
+    }
+}
+
+/// The cost is the negated score, since higher scores are better.
+impl SwapCost for SwapScorer {
+    fn cost(&self) -> f64 {
+        -self.score()
+    }
+
+    fn swap_delta(&self, first: (usize, usize), second: (usize, usize)) -> f64 {
+        -SwapScorer::swap_delta(self, first, second)
+    }
+
+    fn swap(&mut self, first: (usize, usize), second: (usize, usize)) {
+        SwapScorer::swap(self, first, second);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemperatureSchedule {
+    /// Cools by the same factor every iteration, from `initial` to `last`.
+    Exponential { initial: f64, last: f64 },
+    /// Cools by the same amount every iteration, from `initial` to `last`.
+    Linear { initial: f64, last: f64 },
+}
+
+impl TemperatureSchedule {
+    /// The temperature once `progress` (from 0 to 1) of the iterations are done.
+    pub fn temperature(&self, progress: f64) -> f64 {
+        match *self {
+            TemperatureSchedule::Exponential { initial, last } => {
+                initial * (last / initial).powf(progress)
+            }
+            TemperatureSchedule::Linear { initial, last } => initial + (last - initial) * progress,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulatedAnnealing {
+    pub schedule: TemperatureSchedule,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for SimulatedAnnealing {
+    fn default() -> Self {
+        Self {
+            // The evaluator's metrics are shares between 0 and 1, so a swap rarely changes the cost by more than a few hundredths.
+            schedule: TemperatureSchedule::Exponential {
+                initial: 0.05,
+                last: 0.0001,
+            },
+            iterations: 100_000,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnnealingResult {
+    /// The lowest cost layout that was seen, which isn't necessarily the last one.
+    pub layout: KeyboardLayout,
+    pub cost: f64,
+    pub accepted_swaps: usize,
+}
+
+impl SimulatedAnnealing {
+    pub fn new(schedule: TemperatureSchedule, iterations: usize, seed: u64) -> Self {
+        Self {
+            schedule,
+            iterations,
+            seed,
+        }
+    }
+
+    /// Looks for the layout with the lowest cost, starting from `start`. This works out the cost of the whole layout for every
+    /// swap, so `optimize_swaps` is quicker where the cost can be updated a swap at a time.
+    pub fn optimize(
+        &self,
+        start: KeyboardLayout,
+        cost: impl Fn(&KeyboardLayout) -> f64,
+    ) -> AnnealingResult {
+        let mut cost = FullCost::new(start.clone(), cost);
+        self.optimize_swaps(start, &mut cost)
+    }
+
+    /// Looks for the layout with the lowest cost, starting from `start`, which `cost` should start out at as well.
+    pub fn optimize_swaps(
+        &self,
+        start: KeyboardLayout,
+        cost: &mut impl SwapCost,
+    ) -> AnnealingResult {
+        let mut random = Random::new(self.seed);
+        let positions: Vec<_> = start.positions().collect();
+        let mut layout = start;
+        let mut current_cost = cost.cost();
+        let mut best = AnnealingResult {
+            layout: layout.clone(),
+            cost: current_cost,
+            accepted_swaps: 0,
+        };
+        if positions.len() < 2 {
+            return best;
+        }
+        for iteration in 0..self.iterations {
+            let first = random.below(positions.len());
+            // Picking from the other positions makes sure the two positions are different.
+            let second = (first + 1 + random.below(positions.len() - 1)) % positions.len();
+            let (first, second) = (positions[first], positions[second]);
+            let delta = cost.swap_delta(first, second);
+            let temperature = self
+                .schedule
+                .temperature(iteration as f64 / self.iterations as f64);
+            let accept = delta <= 0.0 || random.fraction() < (-delta / temperature).exp();
+            if accept {
+                cost.swap(first, second);
+                layout.swap(first, second);
+                current_cost = cost.cost();
+                best.accepted_swaps += 1;
+                if current_cost < best.cost {
+                    best.layout = layout.clone();
+                    best.cost = current_cost;
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluator::{KeyStatistics, LayoutEvaluator},
+        keyboard::KeyCode,
+    };
+
+    fn statistics() -> KeyStatistics {
+        let mut statistics = KeyStatistics::default();
+        for (index, key_code) in KeyCode::ALL[..30].iter().enumerate() {
+            statistics.unigrams.insert(*key_code, (30 - index) as f64);
+            let next = KeyCode::ALL[(index * 7 + 3) % 30];
+            statistics
+                .bigrams
+                .insert((*key_code, next), (index % 4 + 1) as f64);
+        }
+        statistics
+    }
+
+    fn annealing(seed: u64) -> SimulatedAnnealing {
+        SimulatedAnnealing {
+            iterations: 2000,
+            seed,
+            ..SimulatedAnnealing::default()
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_layout() {
+        let statistics = statistics();
+        let evaluator = LayoutEvaluator::default();
+        let start = KeyboardLayout::qwerty();
+        let run = |seed| {
+            let mut scorer = evaluator.swap_scorer(&start, &statistics);
+            annealing(seed).optimize_swaps(start.clone(), &mut scorer)
+        };
+        let (first, second) = (run(3), run(3));
+        assert_eq!(first.layout, second.layout);
+        assert_eq!(first.accepted_swaps, second.accepted_swaps);
+        assert_ne!(run(4).layout, first.layout);
+    }
+
+    #[test]
+    fn the_result_is_never_worse_than_the_start() {
+        let statistics = statistics();
+        let evaluator = LayoutEvaluator::default();
+        let cost = |layout: &KeyboardLayout| -evaluator.score(layout, &statistics);
+        for start in [
+            KeyboardLayout::qwerty(),
+            KeyboardLayout::qwerty_main_block(),
+        ] {
+            let mut scorer = evaluator.swap_scorer(&start, &statistics);
+            let result = annealing(0).optimize_swaps(start.clone(), &mut scorer);
+            assert!(result.cost <= cost(&start));
+            // The reported cost is the layout's, even after thousands of swaps.
+            assert!((result.cost - cost(&result.layout)).abs() < 1e-9);
+        }
+        // Even when every swap is kept, the start is remembered if nothing beats it.
+        let result = SimulatedAnnealing::new(
+            TemperatureSchedule::Linear {
+                initial: 1e9,
+                last: 1e9,
+            },
+            100,
+            0,
+        )
+        .optimize(KeyboardLayout::qwerty(), |layout| {
+            if *layout == KeyboardLayout::qwerty() {
+                0.0
+            } else {
+                1.0
+            }
+        });
+        assert_eq!(result.layout, KeyboardLayout::qwerty());
+        assert_eq!(result.cost, 0.0);
+    }
+}
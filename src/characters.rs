@@ -0,0 +1,289 @@
+//! Characters, as opposed to the physical keys that type them.
+//!
+//! What a key types depends on the keyboard layout that the system is set to: the key named `Y` types `z` on a German
+//! keyboard. A `CharacterMap` says what each keystroke types on one of those system layouts, so that text can be turned into
+//! keys (and keys back into text) on keyboards other than US QWERTY. A `CharacterLayout` places characters instead of keys,
+//! so it can hold anything the system layout can type, like `ä` or `ß`. It is turned into a `LayeredLayout` to be used.
+//!
+//! Dead keys (like `^` on a German keyboard) are left out of the maps, since they don't type anything on their own.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt::Display,
+};
+
+use crate::{
+    keyboard::{KeyCode, KeyboardLayout, Keystroke},
+    layers::{Layer, LayeredLayout},
+};
+
+/// What the keys which type characters type on US QWERTY, from the number row down. The strings given to
+/// `CharacterMap::from_strings` go through the keys in the same order.
+const US_BASE: &str = "`1234567890-=qwertyuiop[]\\asdfghjkl;'zxcvbnm,./";
+const US_SHIFT: &str = "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\"ZXCVBNM<>?";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharacterMap {
+    characters: BTreeMap<Keystroke, char>,
+    keystrokes: BTreeMap<char, Keystroke>,
+}
+
+impl Default for CharacterMap {
+    fn default() -> Self {
+        Self::us()
+    }
+}
+
+fn modifier_count(keystroke: Keystroke) -> usize {
+    keystroke.shift as usize + keystroke.alt_gr as usize
+}
+
+impl CharacterMap {
+    pub fn new(characters: BTreeMap<Keystroke, char>) -> Self {
+        let mut keystrokes: BTreeMap<char, Keystroke> = BTreeMap::new();
+        for (&keystroke, &character) in &characters {
+            // When more than one keystroke types a character, the one with the fewest modifiers is used.
+            let best = keystrokes.entry(character).or_insert(keystroke);
+            if modifier_count(keystroke) < modifier_count(*best) {
+                *best = keystroke;
+            }
+        }
+        Self {
+            characters,
+            keystrokes,
+        }
+    }
+
+    /// Builds a map from what each key types on its own and with Shift, given in the same order as `US_BASE`, with a space for
+    /// keys that don't type anything. Space, Enter and Tab are the same everywhere, so they are added as well.
+    fn from_strings(base: &str, shift: &str, alt_gr: &[(KeyCode, char)]) -> Self {
+        let mut characters = BTreeMap::from([
+            (Keystroke::new(KeyCode::Space), ' '),
+            (Keystroke::new(KeyCode::Enter), '\n'),
+            (Keystroke::new(KeyCode::Tab), '\t'),
+        ]);
+        for ((us_character, base_character), shift_character) in
+            US_BASE.chars().zip(base.chars()).zip(shift.chars())
+        {
+            let key_code = KeyCode::from_character(us_character).unwrap();
+            if base_character != ' ' {
+                characters.insert(Keystroke::new(key_code), base_character);
+            }
+            if shift_character != ' ' {
+                characters.insert(Keystroke::shifted(key_code), shift_character);
+            }
+        }
+        for &(key_code, character) in alt_gr {
+            let keystroke = Keystroke {
+                alt_gr: true,
+                ..Keystroke::new(key_code)
+            };
+            characters.insert(keystroke, character);
+        }
+        Self::new(characters)
+    }
+
+    pub fn us() -> Self {
+        Self::from_strings(US_BASE, US_SHIFT, &[])
+    }
+
+    /// The German QWERTZ layout. The `<>|` key of ISO keyboards is missing, since there is no key code for it.
+    pub fn qwertz() -> Self {
+        Self::from_strings(
+            " 1234567890ß qwertzuiopü+#asdfghjklöäyxcvbnm,.-",
+            "°!\"§$%&/()=? QWERTZUIOPÜ*'ASDFGHJKLÖÄYXCVBNM;:_",
+            &[
+                (KeyCode::Digit2, '²'),
+                (KeyCode::Digit3, '³'),
+                (KeyCode::Digit7, '{'),
+                (KeyCode::Digit8, '['),
+                (KeyCode::Digit9, ']'),
+                (KeyCode::Digit0, '}'),
+                (KeyCode::Minus, '\\'),
+                (KeyCode::Q, '@'),
+                (KeyCode::E, '€'),
+                (KeyCode::RightBracket, '~'),
+                (KeyCode::M, 'µ'),
+            ],
+        )
+    }
+
+    /// The French AZERTY layout. The `<>` key of ISO keyboards is missing, since there is no key code for it.
+    pub fn azerty() -> Self {
+        Self::from_strings(
+            "²&é\"'(-è_çà)=azertyuiop $*qsdfghjklmùwxcvbn,;:!",
+            " 1234567890°+AZERTYUIOP £µQSDFGHJKLM%WXCVBN?./§",
+            &[
+                (KeyCode::Digit3, '#'),
+                (KeyCode::Digit4, '{'),
+                (KeyCode::Digit5, '['),
+                (KeyCode::Digit6, '|'),
+                (KeyCode::Digit8, '\\'),
+                (KeyCode::Digit9, '^'),
+                (KeyCode::Digit0, '@'),
+                (KeyCode::Minus, ']'),
+                (KeyCode::Equal, '}'),
+                (KeyCode::E, '€'),
+                (KeyCode::RightBracket, '¤'),
+            ],
+        )
+    }
+
+    /// The map for a system layout by its name: `us`, `qwertz` or `azerty`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "us" => Some(Self::us()),
+            "qwertz" => Some(Self::qwertz()),
+            "azerty" => Some(Self::azerty()),
+            _ => None,
+        }
+    }
+
+    pub fn character_of(&self, keystroke: Keystroke) -> Option<char> {
+        self.characters.get(&keystroke).copied()
+    }
+
+    /// The keystroke which types the character, or `None` if this layout can't type it.
+    pub fn keystroke_for(&self, character: char) -> Option<Keystroke> {
+        self.keystrokes.get(&character).copied()
+    }
+}
+
+/// The layers of a `CharacterLayout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShiftLevel {
+    Base,
+    Shift,
+    AltGr,
+}
+
+impl ShiftLevel {
+    pub const ALL: [ShiftLevel; 3] = [ShiftLevel::Base, ShiftLevel::Shift, ShiftLevel::AltGr];
+
+    /// The keys that are held to type on this level.
+    fn held_keys(self) -> BTreeSet<KeyCode> {
+        match self {
+            ShiftLevel::Base => BTreeSet::new(),
+            ShiftLevel::Shift => BTreeSet::from([KeyCode::LeftShift]),
+            ShiftLevel::AltGr => BTreeSet::from([KeyCode::RightAlt]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UntypeableCharacterError(pub char);
+
+impl Display for UntypeableCharacterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' can't be typed with the system's keyboard layout",
+            self.0
+        )
+    }
+}
+
+impl Error for UntypeableCharacterError {}
+
+/// Which character each position types, on its own, with Shift and with AltGr.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CharacterLayout {
+    characters: BTreeMap<ShiftLevel, BTreeMap<(usize, usize), char>>,
+}
+
+impl CharacterLayout {
+    /// What `layout` types on the system layout that `character_map` describes. Keystrokes which don't type a character are
+    /// left out.
+    pub fn from_layered(layout: &LayeredLayout, character_map: &CharacterMap) -> Self {
+        let positions: BTreeSet<_> = layout
+            .base()
+            .positions()
+            .chain(
+                layout
+                    .layers()
+                    .iter()
+                    .flat_map(|layer| layer.keystrokes().map(|(position, _)| position)),
+            )
+            .collect();
+        let mut character_layout = Self::default();
+        for level in ShiftLevel::ALL {
+            let held_keys = level.held_keys();
+            for &position in &positions {
+                if let Some(character) = layout
+                    .keystroke_at(position, &held_keys)
+                    .and_then(|keystroke| character_map.character_of(keystroke))
+                {
+                    character_layout.set_character_at(level, position, character);
+                }
+            }
+        }
+        character_layout
+    }
+
+    pub fn character_at(&self, level: ShiftLevel, position: (usize, usize)) -> Option<char> {
+        self.characters.get(&level)?.get(&position).copied()
+    }
+
+    pub fn set_character_at(
+        &mut self,
+        level: ShiftLevel,
+        position: (usize, usize),
+        character: char,
+    ) {
+        self.characters
+            .entry(level)
+            .or_default()
+            .insert(position, character);
+    }
+
+    /// Each position on the level which has a character, row by row.
+    pub fn characters(
+        &self,
+        level: ShiftLevel,
+    ) -> impl Iterator<Item = ((usize, usize), char)> + '_ {
+        self.characters
+            .get(&level)
+            .into_iter()
+            .flatten()
+            .map(|(&position, &character)| (position, character))
+    }
+
+    /// Turns the characters into the keystrokes which type them on the system layout that `character_map` describes.
+    ///
+    /// Characters which can be typed without modifiers make up the base layer. Base characters which need modifiers (like `:`
+    /// on US QWERTY) go on a layer without activation keys, under the Shift and AltGr layers.
+    pub fn to_layered(
+        &self,
+        character_map: &CharacterMap,
+    ) -> Result<LayeredLayout, UntypeableCharacterError> {
+        let keystroke_for = |character| {
+            character_map
+                .keystroke_for(character)
+                .ok_or(UntypeableCharacterError(character))
+        };
+        let mut base = KeyboardLayout::new(BTreeMap::new());
+        let mut modified_base = Layer::new("Base", Vec::new());
+        for (position, character) in self.characters(ShiftLevel::Base) {
+            let keystroke = keystroke_for(character)?;
+            if keystroke == Keystroke::new(keystroke.key_code) {
+                base.set_key_at(position.0, position.1, keystroke.key_code);
+            } else {
+                modified_base.set_keystroke_at(position, keystroke);
+            }
+        }
+        let mut shift = Layer::shift();
+        for (position, character) in self.characters(ShiftLevel::Shift) {
+            shift.set_keystroke_at(position, keystroke_for(character)?);
+        }
+        let mut alt_gr = Layer::alt_gr();
+        for (position, character) in self.characters(ShiftLevel::AltGr) {
+            alt_gr.set_keystroke_at(position, keystroke_for(character)?);
+        }
+        let mut layers = vec![shift, alt_gr];
+        if modified_base.keystrokes().next().is_some() {
+            layers.insert(0, modified_base);
+        }
+        Ok(LayeredLayout::with_layers(base, layers))
+    }
+}
@@ -0,0 +1,686 @@
+//! The command line interface, for recording and generating layouts without a display.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use keyboard_layout_optimizer::{
+    annealing::SimulatedAnnealing,
+    characters::CharacterMap,
+    corpus::CorpusHint,
+    digram_timing::DigramTimingHint,
+    evaluator::{KeyStatistics, LayoutEvaluator},
+    finger_map::FingerMap,
+    genetic::GeneticSearch,
+    geometry::KeyboardGeometry,
+    key_event_source::KeyEventSource,
+    keyboard::KeyboardLayout,
+    kle::KleKeyboard,
+    layers::LayeredLayout,
+    layout_creator::{AssignmentStrategy, LayoutCreator, Normalization},
+    layout_file::LayoutFile,
+    presets::preset,
+    quadratic_assignment::{QuadraticAssignment, TabuSearch},
+    session::{SessionRecorder, SessionReplayer},
+    trace::{run_event_loop, Tracer},
+};
+
+use crate::{load_digram_timing_hint, SESSION_LOG_PATH};
+
+const USAGE: &str = "Usage: keyboard-layout-optimizer [COMMAND] [OPTIONS]
+
+Without a command, the graphical interface is started.
+
+Commands:
+    record [--log PATH]                 Record keystrokes to a session log until stopped with Ctrl+C
+    generate [DATA] [SEARCH]            Print the layout generated from the data, and save it to a layout file with
+                                        --output PATH
+    score [DATA] LAYOUT                 Print the score of a layout, metric by metric and hint by hint
+    compare [DATA] LAYOUT...            Print the scores of several layouts, best first
+    export [DATA] --output PATH         Combine the data into a single statistics file
+    help                                Print this message
+
+Data options (all can be given several times):
+    --log PATH      Replay a session log
+    --stats PATH    Load a statistics file saved by the graphical interface or by `export`
+    --corpus PATH   Count the characters in a text file, to optimise for text like it
+If none are given, the same files as the graphical interface are used.
+
+Hint options:
+    --hint-weight NAME=WEIGHT   How much a hint (digram_timing or corpus) counts when generating a layout, 1 by default
+    --normalization KIND        How the hints' ranks are rescaled before they are combined: none, min-max (the default),
+                                z-score or rank-order
+    --system-layout NAME        The keyboard layout set in the operating system: us (the default), qwertz or azerty.
+                                It is used for working out which keys type the characters of a corpus, and the file
+                                that generate --output saves has the characters the layout types on it
+    --template NAME             Which keys are placed, and where they can go: letters (the default, QWERTY's three rows
+                                of letters and punctuation), main-block (every key of the main block) or the PATH of a
+                                layout file
+
+Scoring options:
+    --finger-map NAME   Which finger presses each key: standard (the default) or angle-mod
+    --geometry NAME     Where the keys are, for measuring finger travel: ansi (the default), ortholinear or split
+    --kle PATH          Read where the keys are from a keyboard-layout-editor.com JSON file instead. The keys are matched
+                        up with QWERTY's by their legends, and the rest go on row 6, which a --template file can
+                        put keys on
+
+Search options:
+    --optimizer NAME    How to search for the layout: greedy (the default), hungarian, annealing, local, tabu or genetic
+    --iterations N      How many iterations (or generations) the annealing, tabu or genetic optimizer runs for
+    --seed N            The random seed for the annealing, tabu or genetic optimizer, to get the same layout every time
+    --shortlist N       Print the best N different layouts of the genetic optimizer's final population
+    --start LAYOUT      The layout that the annealing, local, tabu or genetic optimizer starts from, instead of the
+                        generated one
+
+The local and tabu optimizers place keys by how quickly you type each pair of positions, and print the average
+time per digram that the layout should give.
+
+Layouts:
+    qwerty          The QWERTY layout
+    generated       The layout generated from the data
+    dvorak, colemak, colemak-dh, workman, halmak, canary, graphite, qwertz, azerty
+                    The letter blocks of other well-known layouts
+    PATH            A layout file, with a row of keys on each line, like the ones `generate --output` and the
+                    graphical interface save";
+
+/// Where the key press statistics come from, and how the hints made from them are combined.
+#[derive(Default)]
+struct DataSources {
+    session_logs: Vec<PathBuf>,
+    statistics_files: Vec<PathBuf>,
+    corpora: Vec<PathBuf>,
+    hint_weights: Vec<(String, f64)>,
+    normalization: Option<Normalization>,
+    finger_map: FingerMap,
+    geometry: KeyboardGeometry,
+    character_map: CharacterMap,
+    /// The name of the system layout that `character_map` is for, if one was given.
+    system_layout: Option<String>,
+    /// The keys that layouts are made of, at the positions they can go in.
+    template: KeyboardLayout,
+}
+
+impl DataSources {
+    fn is_empty(&self) -> bool {
+        self.session_logs.is_empty() && self.statistics_files.is_empty() && self.corpora.is_empty()
+    }
+
+    fn load(&self) -> Result<DigramTimingHint, Box<dyn Error>> {
+        if self.is_empty() {
+            return load_digram_timing_hint();
+        }
+        let mut hint = DigramTimingHint::default();
+        for path in &self.statistics_files {
+            hint.merge(&DigramTimingHint::load(path)?);
+        }
+        for path in &self.session_logs {
+            // Each log is replayed into a hint of its own so that the last key of one log doesn't form a digram with the first key of the next.
+            let mut log_hint = DigramTimingHint::default();
+            log_hint.replay(&SessionReplayer::open(path)?);
+            hint.merge(&log_hint);
+        }
+        Ok(hint)
+    }
+
+    fn load_corpus(&self) -> Result<Option<CorpusHint>, Box<dyn Error>> {
+        if self.corpora.is_empty() {
+            return Ok(None);
+        }
+        let mut hint = CorpusHint::default();
+        hint.set_character_map(self.character_map.clone());
+        for path in &self.corpora {
+            hint.ingest_file(path)?;
+        }
+        Ok(Some(hint))
+    }
+}
+
+/// How `generate` searches for a layout.
+enum Optimizer {
+    Greedy,
+    Hungarian,
+    Annealing(SimulatedAnnealing),
+    LocalSearch,
+    Tabu(TabuSearch),
+    Genetic {
+        search: GeneticSearch,
+        shortlist: usize,
+    },
+}
+
+enum Command {
+    Record {
+        session_log: PathBuf,
+    },
+    Generate {
+        data: DataSources,
+        optimizer: Optimizer,
+        /// The layout to start searching from, if not the generated one.
+        start: Option<String>,
+        /// Where to save the best layout, if anywhere.
+        output: Option<PathBuf>,
+    },
+    Score {
+        data: DataSources,
+        layout: String,
+    },
+    Compare {
+        data: DataSources,
+        layouts: Vec<String>,
+    },
+    Export {
+        data: DataSources,
+        output: PathBuf,
+    },
+    Help,
+}
+
+fn parse_arguments(mut arguments: impl Iterator<Item = String>) -> Result<Command, Box<dyn Error>> {
+    let command = arguments.next().unwrap_or_default();
+    let mut data = DataSources::default();
+    let mut session_log = None;
+    let mut output = None;
+    let mut layouts = Vec::new();
+    let mut optimizer_name = String::from("greedy");
+    let mut iterations = None;
+    let mut seed = None;
+    let mut shortlist = None;
+    let mut start = None;
+    while let Some(argument) = arguments.next() {
+        let mut value = || {
+            arguments
+                .next()
+                .ok_or_else(|| format!("{argument} needs a value"))
+        };
+        match argument.as_str() {
+            "--log" if command == "record" => session_log = Some(PathBuf::from(value()?)),
+            "--log" => data.session_logs.push(value()?.into()),
+            "--stats" => data.statistics_files.push(value()?.into()),
+            "--corpus" => data.corpora.push(value()?.into()),
+            "--hint-weight" => {
+                let value = value()?;
+                let (name, weight) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("Expected NAME=WEIGHT, but got {value}"))?;
+                data.hint_weights.push((name.to_string(), weight.parse()?));
+            }
+            "--finger-map" => {
+                data.finger_map = match value()?.as_str() {
+                    "standard" => FingerMap::STANDARD,
+                    "angle-mod" => FingerMap::ANGLE_MOD,
+                    other => return Err(format!("Unknown finger map {other}").into()),
+                }
+            }
+            "--system-layout" => {
+                let name = value()?;
+                data.character_map = CharacterMap::from_name(&name)
+                    .ok_or_else(|| format!("Unknown system layout {name}"))?;
+                data.system_layout = Some(name);
+            }
+            "--template" => {
+                data.template = match value()?.as_str() {
+                    "letters" => KeyboardLayout::qwerty(),
+                    "main-block" => KeyboardLayout::qwerty_main_block(),
+                    path => LayoutFile::load(path)
+                        .map_err(|error| format!("{path}: {error}"))?
+                        .layout
+                        .base()
+                        .clone(),
+                }
+            }
+            "--geometry" => {
+                data.geometry = match value()?.as_str() {
+                    "ansi" => KeyboardGeometry::ansi(),
+                    "ortholinear" => KeyboardGeometry::ortholinear(),
+                    "split" => KeyboardGeometry::column_staggered_split(),
+                    other => return Err(format!("Unknown geometry {other}").into()),
+                }
+            }
+            "--kle" => {
+                let keyboard = KleKeyboard::load(value()?)?;
+                let qwerty = KeyboardLayout::qwerty_main_block();
+                report_extra_keys(&keyboard, &qwerty);
+                data.geometry = keyboard.geometry_for(&qwerty);
+            }
+            "--normalization" => data.normalization = Some(parse_normalization(&value()?)?),
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--optimizer" => optimizer_name = value()?,
+            "--iterations" => iterations = Some(value()?.parse()?),
+            "--seed" => seed = Some(value()?.parse()?),
+            "--shortlist" => shortlist = Some(value()?.parse()?),
+            "--start" => start = Some(value()?),
+            option if option.starts_with("--") => {
+                return Err(format!("Unknown option {option}").into())
+            }
+            _ => layouts.push(argument),
+        }
+    }
+    let expect_layouts = |count: usize| {
+        if layouts.len() == count {
+            Ok(())
+        } else {
+            Err(format!(
+                "{command} expects {count} layout(s), but got {}",
+                layouts.len()
+            ))
+        }
+    };
+    Ok(match command.as_str() {
+        "record" => {
+            expect_layouts(0)?;
+            Command::Record {
+                session_log: session_log.unwrap_or_else(|| SESSION_LOG_PATH.into()),
+            }
+        }
+        "generate" => {
+            expect_layouts(0)?;
+            let optimizer = match optimizer_name.as_str() {
+                "greedy" => Optimizer::Greedy,
+                "hungarian" => Optimizer::Hungarian,
+                "annealing" => {
+                    let mut annealing = SimulatedAnnealing::default();
+                    annealing.iterations = iterations.unwrap_or(annealing.iterations);
+                    annealing.seed = seed.unwrap_or(annealing.seed);
+                    Optimizer::Annealing(annealing)
+                }
+                "local" => Optimizer::LocalSearch,
+                "tabu" => {
+                    let mut tabu_search = TabuSearch::default();
+                    tabu_search.iterations = iterations.unwrap_or(tabu_search.iterations);
+                    tabu_search.seed = seed.unwrap_or(tabu_search.seed);
+                    Optimizer::Tabu(tabu_search)
+                }
+                "genetic" => {
+                    let mut search = GeneticSearch::default();
+                    search.generations = iterations.unwrap_or(search.generations);
+                    search.seed = seed.unwrap_or(search.seed);
+                    Optimizer::Genetic {
+                        search,
+                        shortlist: shortlist.take().unwrap_or(1),
+                    }
+                }
+                other => return Err(format!("Unknown optimizer {other}").into()),
+            };
+            if shortlist.is_some() {
+                return Err("--shortlist only works with the genetic optimizer".into());
+            }
+            if start.is_some() && matches!(optimizer, Optimizer::Greedy | Optimizer::Hungarian) {
+                return Err(
+                    "--start only works with the annealing, local, tabu and genetic optimizers"
+                        .into(),
+                );
+            }
+            Command::Generate {
+                data,
+                optimizer,
+                start,
+                output,
+            }
+        }
+        "score" => {
+            expect_layouts(1)?;
+            Command::Score {
+                data,
+                layout: layouts.remove(0),
+            }
+        }
+        "compare" => {
+            if layouts.len() < 2 {
+                return Err("compare needs at least two layouts".into());
+            }
+            Command::Compare { data, layouts }
+        }
+        "export" => {
+            expect_layouts(0)?;
+            Command::Export {
+                data,
+                output: output.ok_or("export needs an --output path")?,
+            }
+        }
+        "help" | "--help" | "-h" => Command::Help,
+        other => return Err(format!("Unknown command {other}, see `help` for usage").into()),
+    })
+}
+
+/// Says which keys of a KLE keyboard couldn't be matched up with `layout`'s, and where they went instead, since otherwise
+/// there's no telling what positions a template needs for them.
+pub fn report_extra_keys(keyboard: &KleKeyboard, layout: &KeyboardLayout) {
+    let extra_keys = keyboard.extra_keys(layout);
+    if extra_keys.is_empty() {
+        return;
+    }
+    let described: Vec<String> = extra_keys
+        .iter()
+        .map(|((row, column), key)| format!("{:?} at row {row}, column {column}", key.legend))
+        .collect();
+    eprintln!(
+        "Keys that aren't on QWERTY's main block: {}",
+        described.join(", ")
+    );
+}
+
+fn parse_normalization(name: &str) -> Result<Normalization, Box<dyn Error>> {
+    match name {
+        "none" => Ok(Normalization::None),
+        "min-max" => Ok(Normalization::MinMax),
+        "z-score" => Ok(Normalization::ZScore),
+        "rank-order" => Ok(Normalization::RankOrder),
+        _ => Err(format!("Unknown normalization {name}").into()),
+    }
+}
+
+fn resolve_layout(
+    name: &str,
+    layout_creator: &LayoutCreator,
+) -> Result<KeyboardLayout, Box<dyn Error>> {
+    if name.eq_ignore_ascii_case("generated") {
+        return Ok(layout_creator.create_layout());
+    }
+    if let Some(preset) = preset(name) {
+        return Ok(preset.layout());
+    }
+    if Path::new(name).is_file() {
+        let file = LayoutFile::load(name).map_err(|error| format!("{name}: {error}"))?;
+        return Ok(file.layout.base().clone());
+    }
+    Err(format!("Unknown layout {name}").into())
+}
+
+/// Lays a start layout over the template, so that one which only has some of the template's positions (like a preset's three
+/// rows on the main block) still has all of them. The template's keys that are in the way are swapped out of it.
+fn fill_template(layout: &KeyboardLayout, template: &KeyboardLayout) -> KeyboardLayout {
+    let mut filled = template.clone();
+    for (position, key_code) in layout.keys() {
+        if filled.key_at(position).is_none() {
+            continue;
+        }
+        match filled.position_of(key_code) {
+            Some(current_position) => filled.swap(position, current_position),
+            None => filled.set_key_at(position.0, position.1, key_code),
+        }
+    }
+    filled
+}
+
+struct LoadedData {
+    layout_creator: LayoutCreator,
+    /// The key statistics, for evaluating layouts with.
+    statistics: KeyStatistics,
+    evaluator: LayoutEvaluator,
+    /// The digrams and position timings, for the local and tabu optimizers.
+    quadratic_assignment: QuadraticAssignment,
+}
+
+/// Loads the hints into a layout creator, and gathers their statistics.
+fn load_data(data: &DataSources) -> Result<LoadedData, Box<dyn Error>> {
+    let digram_timing_hint = data.load()?;
+    // Each source is normalized over the template's keys only, so that keys which aren't being placed don't change how much
+    // it counts.
+    let layout = &data.template;
+    let mut statistics = KeyStatistics::from(&digram_timing_hint)
+        .restricted_to(layout)
+        .normalized();
+    let corpus_hint = data.load_corpus()?;
+    if let Some(corpus_hint) = &corpus_hint {
+        statistics.merge(
+            &KeyStatistics::from(corpus_hint)
+                .restricted_to(layout)
+                .normalized(),
+        );
+    }
+    let quadratic_assignment = QuadraticAssignment::from_timing(
+        &statistics,
+        &digram_timing_hint,
+        &layout.positions().collect::<Vec<_>>(),
+    );
+    let normalization = data.normalization.unwrap_or(Normalization::MinMax);
+    let mut layout_creator = LayoutCreator::new(Vec::new());
+    layout_creator.set_template(data.template.clone());
+    layout_creator.add_hint(
+        "digram_timing",
+        Box::new(digram_timing_hint),
+        1.0,
+        normalization,
+    );
+    if let Some(corpus_hint) = corpus_hint {
+        layout_creator.add_hint("corpus", Box::new(corpus_hint), 1.0, normalization);
+    }
+    for (name, weight) in &data.hint_weights {
+        if !layout_creator.set_weight(name, *weight) {
+            return Err(format!("There is no {name} hint to weight").into());
+        }
+    }
+    let mut evaluator = LayoutEvaluator::default();
+    evaluator.set_finger_map(data.finger_map);
+    evaluator.set_geometry(data.geometry.clone());
+    Ok(LoadedData {
+        layout_creator,
+        statistics,
+        evaluator,
+        quadratic_assignment,
+    })
+}
+
+/// Runs the command given on the command line. The first argument should be the command, not the program name.
+pub fn run(arguments: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    match parse_arguments(arguments)? {
+        Command::Record { session_log } => {
+            let mut session_recorder = SessionRecorder::open(&session_log)?;
+            let mut tracer = Tracer::default();
+            tracer.subscribe(Box::new(move |context, key_code| {
+                if let Err(error) = session_recorder
+                    .record(key_code, context.kind(), Instant::now())
+                    .and_then(|()| session_recorder.flush())
+                {
+                    eprintln!("Failed to record keystroke: {error}");
+                }
+            }));
+            tracer.start()?;
+            println!(
+                "Recording keystrokes to {}, press Ctrl+C to stop",
+                session_log.display()
+            );
+            run_event_loop();
+        }
+        Command::Generate {
+            data,
+            optimizer,
+            start,
+            output,
+        } => {
+            let LoadedData {
+                mut layout_creator,
+                statistics,
+                evaluator,
+                quadratic_assignment,
+            } = load_data(&data)?;
+            // Only the optimizers which search from a layout need one, and making it can take a while.
+            let start_layout = || -> Result<KeyboardLayout, Box<dyn Error>> {
+                match &start {
+                    Some(name) => Ok(fill_template(
+                        &resolve_layout(name, &layout_creator)?,
+                        layout_creator.template(),
+                    )),
+                    None => Ok(layout_creator.create_layout()),
+                }
+            };
+            let layouts = match optimizer {
+                Optimizer::Greedy => vec![layout_creator.create_layout()],
+                Optimizer::Hungarian => {
+                    layout_creator.set_strategy(AssignmentStrategy::Hungarian);
+                    vec![layout_creator.create_layout()]
+                }
+                Optimizer::Annealing(annealing) => {
+                    let start = start_layout()?;
+                    let mut scorer = evaluator.swap_scorer(&start, &statistics);
+                    let result = annealing.optimize_swaps(start, &mut scorer);
+                    eprintln!(
+                        "Accepted {} of {} swaps, final score {:.4}",
+                        result.accepted_swaps, annealing.iterations, -result.cost
+                    );
+                    vec![result.layout]
+                }
+                Optimizer::LocalSearch => {
+                    let result = quadratic_assignment.local_search(start_layout()?);
+                    eprintln!("Final cost {:.1} ms per digram", result.cost * 1000.0);
+                    vec![result.layout]
+                }
+                Optimizer::Tabu(tabu_search) => {
+                    let result = tabu_search.optimize(&quadratic_assignment, start_layout()?);
+                    eprintln!("Final cost {:.1} ms per digram", result.cost * 1000.0);
+                    vec![result.layout]
+                }
+                Optimizer::Genetic { search, shortlist } => {
+                    let population = search.evolve(start_layout()?, |layout| {
+                        evaluator.score(layout, &statistics)
+                    });
+                    let shortlist = population.shortlist(shortlist);
+                    let scores: Vec<String> = shortlist
+                        .iter()
+                        .map(|scored_layout| format!("{:.4}", scored_layout.fitness))
+                        .collect();
+                    eprintln!("Scores: {}", scores.join(", "));
+                    shortlist
+                        .into_iter()
+                        .map(|scored_layout| scored_layout.layout)
+                        .collect()
+                }
+            };
+            if let Some(output) = output {
+                let mut file = LayoutFile::new(layouts[0].clone());
+                if let Some(system_layout) = &data.system_layout {
+                    file.set("characters", system_layout);
+                }
+                file.save(output)?;
+            }
+            let layouts: Vec<String> = layouts
+                .into_iter()
+                .map(|layout| LayeredLayout::new(layout).to_string())
+                .collect();
+            println!("{}", layouts.join("\n\n"));
+        }
+        Command::Score { data, layout } => {
+            let LoadedData {
+                layout_creator,
+                statistics,
+                evaluator,
+                ..
+            } = load_data(&data)?;
+            let layout = resolve_layout(&layout, &layout_creator)?;
+            let evaluation = evaluator.evaluate(&layout, &statistics);
+            println!("metric\tvalue\tweight\tcontribution");
+            for metric in &evaluation.metrics {
+                println!(
+                    "{}\t{:.4}\t{}\t{:.4}",
+                    metric.name,
+                    metric.value,
+                    metric.weight,
+                    metric.contribution()
+                );
+            }
+            println!("total\t\t\t{:.4}", evaluation.total);
+            println!();
+            println!("hint\tcontribution");
+            let mut hint_totals: Vec<(String, f64)> = layout_creator
+                .hint_names()
+                .map(|name| (name.to_string(), 0.0))
+                .collect();
+            for (position, key) in layout.keys() {
+                for (contribution, (_, total)) in layout_creator
+                    .contributions(position, key)
+                    .iter()
+                    .zip(&mut hint_totals)
+                {
+                    *total += contribution.contribution();
+                }
+            }
+            for (name, total) in hint_totals {
+                println!("{name}\t{total:.4}");
+            }
+        }
+        Command::Compare { data, layouts } => {
+            let LoadedData {
+                layout_creator,
+                statistics,
+                evaluator,
+                ..
+            } = load_data(&data)?;
+            let mut scores = Vec::new();
+            for name in layouts {
+                let layout = resolve_layout(&name, &layout_creator)?;
+                scores.push((name, evaluator.score(&layout, &statistics)));
+            }
+            scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            for (name, score) in scores {
+                println!("{name}\t{score:.4}");
+            }
+        }
+        Command::Export { data, output } => {
+            data.load()?.save(output)?;
+        }
+        Command::Help => println!("{USAGE}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use keyboard_layout_optimizer::{keyboard::KeyCode, presets::DVORAK};
+
+    use super::*;
+
+    fn parse(arguments: &str) -> Result<Command, Box<dyn Error>> {
+        parse_arguments(arguments.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn generate_options_are_parsed() {
+        let command =
+            parse("generate --optimizer tabu --iterations 5 --seed 3 --start dvorak").unwrap();
+        let Command::Generate {
+            optimizer: Optimizer::Tabu(tabu_search),
+            start,
+            output: None,
+            ..
+        } = command
+        else {
+            panic!("not a tabu search");
+        };
+        assert_eq!((tabu_search.iterations, tabu_search.seed), (5, 3));
+        assert_eq!(start.as_deref(), Some("dvorak"));
+    }
+
+    #[test]
+    fn options_that_dont_apply_are_errors() {
+        assert!(parse("generate --start dvorak").is_err());
+        assert!(parse("generate --optimizer annealing --shortlist 3").is_err());
+        assert!(parse("generate --optimizer sideways").is_err());
+        assert!(parse("generate --iterations").is_err());
+        assert!(parse("generate --colour red").is_err());
+        assert!(parse("compare qwerty").is_err());
+        assert!(parse("score").is_err());
+        assert!(parse("export").is_err());
+    }
+
+    #[test]
+    fn start_layouts_fill_the_template() {
+        let template = KeyboardLayout::qwerty_main_block();
+        let start = fill_template(&DVORAK.layout(), &template);
+        assert_eq!(start.len(), template.len());
+        let mut keys: Vec<_> = start.iter().collect();
+        keys.sort();
+        let mut template_keys: Vec<_> = template.iter().collect();
+        template_keys.sort();
+        assert_eq!(keys, template_keys);
+        for (position, key_code) in DVORAK.layout().keys() {
+            assert_eq!(start.key_at(position), Some(key_code));
+        }
+        // Dvorak has no room for the slash in its letter block, so it takes the quote's place.
+        assert_eq!(
+            start.position_of(KeyCode::Slash),
+            template.position_of(KeyCode::from_character('\'').unwrap())
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Provides a `LayoutHint` implementation that ranks keys by how often they appear in a body of text.
+//!
+//! This makes it possible to optimise for a particular kind of typing (source code, prose, chat) without having to type it first.
+//! Each character is counted as the key which types it on the system's keyboard layout (US QWERTY unless another
+//! `CharacterMap` is set), so shifted characters count as their unshifted key (and Shift itself isn't counted). Characters
+//! without a key break up the n-grams, since the keys on either side of them aren't typed one after the other.
+//!
+//! As a hint, a position's ranks can't depend on where the layout creator ends up putting the other keys. Instead, the hint
+//! works out where it would put them itself going by the key counts alone, and ranks keys lower on a finger which that leaves
+//! them sharing with keys they are often typed next to (as bigrams) or one key apart from (in the middle of trigrams). The
+//! bigram counts are also used for scoring whole layouts, through `KeyStatistics` in the evaluator and the local and tabu
+//! optimizers.
+
+use std::{collections::BTreeMap, fs, io, path::Path, time::Instant};
+
+use crate::{
+    characters::CharacterMap, finger_map::FingerMap, keyboard::KeyCode, layout_creator::LayoutHint,
+};
+
+/// How easy each position is to reach, from 0 (hard) to 1 (easy).
+///
+/// The home row is the easiest, followed by the top row, with the index and middle fingers favoured over the others. Positions
+/// outside the letter block get `OUTER_POSITION_EASE`.
+const POSITION_EASE: [[f64; 10]; 3] = [
+    [0.4, 0.6, 0.8, 0.7, 0.5, 0.5, 0.7, 0.8, 0.6, 0.4],
+    [0.7, 0.8, 0.9, 1.0, 0.7, 0.7, 1.0, 0.9, 0.8, 0.7],
+    [0.3, 0.4, 0.5, 0.6, 0.3, 0.4, 0.6, 0.5, 0.4, 0.3],
+];
+const OUTER_POSITION_EASE: f64 = 0.2;
+/// How much a key's rank drops for each share of the bigrams that it would type with the same finger as a key next to it.
+const SAME_FINGER_BIGRAM_PENALTY: f64 = 1.0;
+/// The same for keys one apart in a trigram, which are less of a stretch since the finger has a key's time to move.
+const SAME_FINGER_SKIPGRAM_PENALTY: f64 = 0.5;
+
+#[derive(Debug, Clone, Default)]
+pub struct CorpusHint {
+    unigrams: BTreeMap<KeyCode, usize>,
+    bigrams: BTreeMap<(KeyCode, KeyCode), usize>,
+    trigrams: BTreeMap<(KeyCode, KeyCode, KeyCode), usize>,
+    /// The first and last keys of the trigrams.
+    skipgrams: BTreeMap<(KeyCode, KeyCode), usize>,
+    total_unigrams: usize,
+    character_map: CharacterMap,
+}
+
+impl CorpusHint {
+    /// Sets which keys type which characters. This only applies to text ingested afterwards.
+    pub fn set_character_map(&mut self, character_map: CharacterMap) {
+        self.character_map = character_map;
+    }
+
+    pub fn ingest_text(&mut self, text: &str) {
+        let mut previous: Option<KeyCode> = None;
+        let mut before_previous: Option<KeyCode> = None;
+        for character in text.chars() {
+            let Some(key_code) = self
+                .character_map
+                .keystroke_for(character)
+                .map(|keystroke| keystroke.key_code)
+            else {
+                previous = None;
+                before_previous = None;
+                continue;
+            };
+            *self.unigrams.entry(key_code).or_insert(0) += 1;
+            self.total_unigrams += 1;
+            if let Some(previous) = previous {
+                *self.bigrams.entry((previous, key_code)).or_insert(0) += 1;
+                if let Some(before_previous) = before_previous {
+                    *self
+                        .trigrams
+                        .entry((before_previous, previous, key_code))
+                        .or_insert(0) += 1;
+                    *self
+                        .skipgrams
+                        .entry((before_previous, key_code))
+                        .or_insert(0) += 1;
+                }
+            }
+            before_previous = previous;
+            previous = Some(key_code);
+        }
+    }
+
+    /// Reads a text file into the corpus. Invalid UTF-8 is replaced rather than rejected, since it can't map to a key anyway.
+    pub fn ingest_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = fs::read(path)?;
+        self.ingest_text(&String::from_utf8_lossy(&bytes));
+        Ok(())
+    }
+
+    pub fn unigram_count(&self, key_code: KeyCode) -> usize {
+        self.unigrams.get(&key_code).copied().unwrap_or(0)
+    }
+
+    pub fn bigram_count(&self, first: KeyCode, second: KeyCode) -> usize {
+        self.bigrams.get(&(first, second)).copied().unwrap_or(0)
+    }
+
+    pub fn trigram_count(&self, first: KeyCode, second: KeyCode, third: KeyCode) -> usize {
+        self.trigrams
+            .get(&(first, second, third))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn unigrams(&self) -> impl Iterator<Item = (KeyCode, usize)> + '_ {
+        self.unigrams
+            .iter()
+            .map(|(&key_code, &count)| (key_code, count))
+    }
+
+    pub fn bigrams(&self) -> impl Iterator<Item = ((KeyCode, KeyCode), usize)> + '_ {
+        self.bigrams.iter().map(|(&bigram, &count)| (bigram, count))
+    }
+
+    pub fn trigrams(&self) -> impl Iterator<Item = ((KeyCode, KeyCode, KeyCode), usize)> + '_ {
+        self.trigrams
+            .iter()
+            .map(|(&trigram, &count)| (trigram, count))
+    }
+
+    /// The number of characters in the corpus which map to a key.
+    pub fn total_characters(&self) -> usize {
+        self.total_unigrams
+    }
+
+    fn ease_of(position: (usize, usize)) -> f64 {
+        POSITION_EASE
+            .get(position.0)
+            .and_then(|row| row.get(position.1))
+            .copied()
+            .unwrap_or(OUTER_POSITION_EASE)
+    }
+
+    /// Where the keys would go if only their counts mattered: the most common key on the easiest position and so on, across
+    /// the letter block.
+    fn placement_by_counts(&self) -> BTreeMap<(usize, usize), KeyCode> {
+        let mut positions: Vec<(usize, usize)> = (0..POSITION_EASE.len())
+            .flat_map(|row| (0..POSITION_EASE[row].len()).map(move |column| (row, column)))
+            .collect();
+        positions.sort_by(|&first, &second| Self::ease_of(second).total_cmp(&Self::ease_of(first)));
+        let mut keys: Vec<(KeyCode, usize)> = self.unigrams().collect();
+        keys.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        positions
+            .into_iter()
+            .zip(keys.into_iter().map(|(key_code, _)| key_code))
+            .collect()
+    }
+
+    /// The shares of bigrams and of trigrams that have the two keys next to each other, or one apart, in either order.
+    fn pair_shares(&self, first: KeyCode, second: KeyCode) -> (f64, f64) {
+        let total_bigrams: usize = self.bigrams.values().sum();
+        let total_skipgrams: usize = self.skipgrams.values().sum();
+        let bigrams = self.bigram_count(first, second) + self.bigram_count(second, first);
+        let skipgram_count = |pair| self.skipgrams.get(&pair).copied().unwrap_or(0);
+        let skipgrams = skipgram_count((first, second)) + skipgram_count((second, first));
+        let share = |count: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            }
+        };
+        (
+            share(bigrams, total_bigrams),
+            share(skipgrams, total_skipgrams),
+        )
+    }
+}
+
+impl LayoutHint for CorpusHint {
+    fn receive_key_press(&mut self, _key_code: KeyCode, _time: Instant) {
+        // The corpus is all we go by, so live typing is ignored.
+    }
+
+    /// Ranks each key by how often it is typed, times how easy the position is, less a penalty for the bigrams and trigrams
+    /// it would share a finger with (see the module documentation).
+    fn rank_keys_for_position(&self, position: (usize, usize)) -> BTreeMap<KeyCode, f64> {
+        if self.total_unigrams == 0 {
+            return BTreeMap::new();
+        }
+        let ease = Self::ease_of(position);
+        let finger_map = FingerMap::default();
+        let finger = finger_map.finger_of(position);
+        let same_finger_keys: Vec<KeyCode> = self
+            .placement_by_counts()
+            .into_iter()
+            .filter(|&(other, _)| other != position && finger_map.finger_of(other) == finger)
+            .map(|(_, key_code)| key_code)
+            .collect();
+        self.unigrams
+            .iter()
+            .map(|(&key, &count)| {
+                let penalty: f64 = same_finger_keys
+                    .iter()
+                    .filter(|&&other| other != key)
+                    .map(|&other| {
+                        let (bigrams, skipgrams) = self.pair_shares(key, other);
+                        SAME_FINGER_BIGRAM_PENALTY * bigrams
+                            + SAME_FINGER_SKIPGRAM_PENALTY * skipgrams
+                    })
+                    .sum();
+                let share = count as f64 / self.total_unigrams as f64;
+                (key, ease * share - penalty)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hint(text: &str) -> CorpusHint {
+        let mut hint = CorpusHint::default();
+        hint.ingest_text(text);
+        hint
+    }
+
+    #[test]
+    fn characters_are_counted_as_their_keys() {
+        let hint = hint("Ab:a é ba");
+        // A capital letter and a shifted symbol count as their keys, and é has no key on US QWERTY.
+        assert_eq!(hint.unigram_count(KeyCode::A), 3);
+        assert_eq!(hint.unigram_count(KeyCode::Semicolon), 1);
+        assert_eq!(hint.unigram_count(KeyCode::Space), 2);
+        assert_eq!(hint.total_characters(), 8);
+        assert_eq!(hint.bigram_count(KeyCode::A, KeyCode::B), 1);
+        assert_eq!(hint.bigram_count(KeyCode::B, KeyCode::Semicolon), 1);
+        assert_eq!(
+            hint.trigram_count(KeyCode::A, KeyCode::B, KeyCode::Semicolon),
+            1
+        );
+        // The é breaks up the n-grams around it.
+        assert_eq!(hint.bigram_count(KeyCode::Space, KeyCode::Space), 0);
+        assert_eq!(
+            hint.trigram_count(KeyCode::A, KeyCode::Space, KeyCode::Space),
+            0
+        );
+    }
+
+    #[test]
+    fn the_character_map_decides_the_keys() {
+        let mut hint = CorpusHint::default();
+        hint.set_character_map(CharacterMap::qwertz());
+        hint.ingest_text("zy");
+        assert_eq!(hint.bigram_count(KeyCode::Y, KeyCode::Z), 1);
+    }
+
+    #[test]
+    fn common_keys_rank_highest_on_easy_positions() {
+        let hint = hint("eeeeeeeet");
+        let ranks = hint.rank_keys_for_position((1, 3));
+        assert!(ranks[&KeyCode::E] > ranks[&KeyCode::T]);
+        assert!(hint.rank_keys_for_position((2, 0))[&KeyCode::E] < ranks[&KeyCode::E]);
+        assert!(CorpusHint::default()
+            .rank_keys_for_position((1, 3))
+            .is_empty());
+    }
+
+    #[test]
+    fn keys_typed_together_rank_lower_on_the_same_finger() {
+        // E is the most common key, so going by counts it goes on the left index finger's home position. H and D are typed as
+        // often as each other, but H is typed next to E.
+        let hint = hint("eeeeeeee he he do do");
+        assert_eq!(hint.placement_by_counts()[&(1, 3)], KeyCode::E);
+        let left_index = hint.rank_keys_for_position((0, 3));
+        assert!(left_index[&KeyCode::H] < left_index[&KeyCode::D]);
+        // Too few keys are typed for any to go on the left pinky, so there is nothing to share it with.
+        let left_pinky = hint.rank_keys_for_position((0, 0));
+        assert_eq!(left_pinky[&KeyCode::H], left_pinky[&KeyCode::D]);
+    }
+}
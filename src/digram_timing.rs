@@ -4,69 +4,605 @@
 //! In theory it should assign the most common digrams to the fastest key pairs.
 //!
 //! In practice, the way we do this is to measure how fast it is (on average) to switch to and from each key position.
-//! We also record how frequently each key is used.
+//! We also record how frequently each key and each digram is used, and how long each transition between two positions takes.
+//!
+//! The statistics can be saved to a plain text file, which starts with a version line and then has one statistic per line:
+//! ```text
+//! digram-timing-statistics 7
+//! log_length 48213
+//! last_updated 1760000000000000
+//! frequency E 1200
+//! digram T H 310
+//! time_to 1 3 152000 950.000
+//! time_from 1 3 148000 946.718
+//! transition 1 3 1 6 131000 24000 85 84.2 60:10.5 61:40.1 62:33.6
+//! ```
+//! Positions are where the keys physically are (see `KeyCode::position`). Times are in microseconds,
+//! and the last number on the timing lines is the total weight of the samples the average was taken over, after decay.
+//! Transition lines give the position typed first, then the second one, then the mean time, its standard deviation, the
+//! number of samples, their total weight after decay, and the weight in each bucket of times (see `TransitionTiming`).
+//! The log length is how much of the session log the statistics were taken from, so that only newer keystrokes need replaying.
+//! The last updated time is the wall clock time (in microseconds since the Unix epoch) that the weights have been decayed up
+//! to.
+//!
+//! Typing times are noisy, so samples which look like hesitations can be left out and old samples can be made to count for
+//! less. See `TimingSettings`.
 
 use std::{
     collections::BTreeMap,
-    time::{Duration, Instant},
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    keyboard::{KeyCode, KeyboardLayout},
-    layout_creator::LayoutHint,
+    keyboard::KeyCode, layout_creator::LayoutHint, session::SessionReplayer,
+    transition_timing::TransitionTiming,
 };
 
+const STATISTICS_HEADER: &str = "digram-timing-statistics";
+/// Version 6 files don't say when they were last updated, and their timing lines have sample counts rather than weights.
+/// Version 5 files don't say how much of the session log they include. Version 4 files only have the letter keys. Version 3
+/// files have transitions without their weight or buckets. Version 2 files don't have any transitions, and version 1 files
+/// don't have any digram counts either.
+const STATISTICS_VERSION: u32 = 7;
+const OLDEST_STATISTICS_VERSION: u32 = 1;
+
+/// Timings are decayed at most this often, since it goes through all of them.
+const DECAY_STEP: Duration = Duration::from_secs(60 * 60);
+
 /// Helper function to allow for adjusting the averages.
+///
+/// The new duration has a weight of 1, out of `new_weight` for the average with it.
 fn adjust_average_duration(
     previous_average: Duration,
     new_duration: Duration,
-    new_count: usize,
+    new_weight: f64,
 ) -> Duration {
-    if new_duration > previous_average {
-        previous_average + (new_duration - previous_average) / new_count as u32
-    } else {
-        previous_average - (previous_average - new_duration) / new_count as u32
+    let share = 1.0 / new_weight;
+    Duration::from_secs_f64(
+        previous_average.as_secs_f64()
+            + (new_duration.as_secs_f64() - previous_average.as_secs_f64()) * share,
+    )
+}
+
+/// How key presses are turned into timing statistics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingSettings {
+    /// Key presses further apart than this aren't counted as a digram at all, since something else probably happened in between.
+    pub max_interval: Duration,
+    /// Times more than this many times slower (or faster) than the median for their transition are left out of the timings.
+    /// `None` keeps every time.
+    pub outlier_factor: Option<f64>,
+    /// How many samples a transition needs before its median is trusted for rejecting outliers.
+    pub min_samples_for_rejection: usize,
+    /// How long it takes for a sample to count for half as much as a new one. Everything decays with the time that passes,
+    /// whether it is being typed or not. `None` keeps a plain average.
+    pub half_life: Option<Duration>,
+}
+
+impl Default for TimingSettings {
+    fn default() -> Self {
+        Self {
+            max_interval: Duration::from_secs(1),
+            outlier_factor: Some(3.0),
+            min_samples_for_rejection: 10,
+            half_life: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+        }
     }
 }
 
+/// Two positions, in the order they are typed.
+pub type Transition = ((usize, usize), (usize, usize));
+
 #[derive(Debug, Clone, Default)]
 pub struct DigramTimingHint {
     last_key: Option<KeyCode>,
     last_time: Option<Instant>,
+    /// An instant and the wall clock time it stands for, so that the key press instants can be compared with `last_updated`.
+    clock: Option<(Instant, SystemTime)>,
 
     frequencies: BTreeMap<KeyCode, usize>,
+    digram_frequencies: BTreeMap<(KeyCode, KeyCode), usize>,
     average_time_to: BTreeMap<(usize, usize), Duration>,
     average_time_from: BTreeMap<(usize, usize), Duration>,
+    time_to_weights: BTreeMap<(usize, usize), f64>,
+    time_from_weights: BTreeMap<(usize, usize), f64>,
+    transition_timings: BTreeMap<Transition, TransitionTiming>,
+    rejected_samples: usize,
+    log_length: Option<u64>,
+    /// The wall clock time that the weights have been decayed up to.
+    last_updated: Option<SystemTime>,
+
+    settings: TimingSettings,
+}
+
+/// Combines two averages, weighting each by the weight of the samples it was taken over.
+fn merge_averages(
+    average: Duration,
+    weight: f64,
+    other_average: Duration,
+    other_weight: f64,
+) -> Duration {
+    let total_weight = weight + other_weight;
+    if total_weight == 0.0 {
+        return average;
+    }
+    Duration::from_secs_f64(
+        (average.as_secs_f64() * weight + other_average.as_secs_f64() * other_weight)
+            / total_weight,
+    )
+}
+
+fn invalid_data(line_number: usize, message: impl Display) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("Line {line_number}: {message}"),
+    )
+}
+
+fn parse_field<T: FromStr>(line_number: usize, field: Option<&str>) -> io::Result<T>
+where
+    T::Err: Display,
+{
+    let field = field.ok_or_else(|| invalid_data(line_number, "Missing field"))?;
+    field
+        .parse()
+        .map_err(|error| invalid_data(line_number, format!("Invalid value '{field}': {error}")))
+}
+
+fn parse_position<'a>(
+    line_number: usize,
+    fields: &mut impl Iterator<Item = &'a str>,
+) -> io::Result<(usize, usize)> {
+    let position = (
+        parse_field(line_number, fields.next())?,
+        parse_field(line_number, fields.next())?,
+    );
+    if KeyCode::at_position(position).is_none() {
+        return Err(invalid_data(line_number, "Position is off the keyboard"));
+    }
+    Ok(position)
+}
+
+impl DigramTimingHint {
+    pub fn new(settings: TimingSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    pub fn settings(&self) -> &TimingSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: TimingSettings) {
+        self.settings = settings;
+    }
+
+    /// How many digram times have been left out of the timings for being outliers. This isn't saved.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+
+    /// How many bytes of the session log these statistics include, if that's known.
+    pub fn log_length(&self) -> Option<u64> {
+        self.log_length
+    }
+
+    pub fn set_log_length(&mut self, log_length: Option<u64>) {
+        self.log_length = log_length;
+    }
+
+    /// The wall clock time that the timings have been decayed up to. This can be up to an hour behind the newest key press,
+    /// since decaying goes through every timing.
+    pub fn last_updated(&self) -> Option<SystemTime> {
+        self.last_updated
+    }
+
+    /// Feeds the key presses in a session log into the statistics, decaying them by the time that passed while it was
+    /// recorded.
+    pub fn replay(&mut self, replayer: &SessionReplayer) {
+        let start = Instant::now();
+        // A log read from where the statistics left off may not have a session start, but then it carries on from them.
+        if let Some(start_time) = replayer.start_time().or(self.last_updated) {
+            self.clock = Some((start, start_time));
+        }
+        replayer.replay_into(self, start);
+        // The key presses after this are typed now, and the last one from the log isn't typed before them.
+        self.clock = None;
+        self.last_key = None;
+        self.last_time = None;
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_statistics(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_statistics(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_statistics(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{STATISTICS_HEADER} {STATISTICS_VERSION}")?;
+        if let Some(log_length) = self.log_length {
+            writeln!(writer, "log_length {log_length}")?;
+        }
+        if let Some(last_updated) = self.last_updated {
+            let since_epoch = last_updated.duration_since(UNIX_EPOCH).unwrap_or_default();
+            writeln!(writer, "last_updated {}", since_epoch.as_micros())?;
+        }
+        for (key_code, frequency) in &self.frequencies {
+            writeln!(writer, "frequency {key_code} {frequency}")?;
+        }
+        for ((first, second), frequency) in &self.digram_frequencies {
+            writeln!(writer, "digram {first} {second} {frequency}")?;
+        }
+        for ((from, to), timing) in &self.transition_timings {
+            write!(
+                writer,
+                "transition {} {} {} {} {} {} {} {:.3}",
+                from.0,
+                from.1,
+                to.0,
+                to.1,
+                timing.mean().as_micros(),
+                timing.standard_deviation().as_micros(),
+                timing.samples(),
+                timing.weight()
+            )?;
+            for (bucket, weight) in timing.histogram() {
+                write!(writer, " {bucket}:{weight:.3}")?;
+            }
+            writeln!(writer)?;
+        }
+        for (name, averages, weights) in [
+            ("time_to", &self.average_time_to, &self.time_to_weights),
+            (
+                "time_from",
+                &self.average_time_from,
+                &self.time_from_weights,
+            ),
+        ] {
+            for ((row, column), average) in averages {
+                let weight = weights.get(&(*row, *column)).copied().unwrap_or(0.0);
+                writeln!(
+                    writer,
+                    "{name} {row} {column} {} {weight:.3}",
+                    average.as_micros()
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads statistics written by `write_statistics`. The key press history isn't saved, so the next key press starts a new digram.
+    pub fn read_statistics(reader: impl BufRead) -> io::Result<Self> {
+        let mut hint = Self::default();
+        let mut lines = reader.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let mut header_fields = header.split_whitespace();
+        if header_fields.next() != Some(STATISTICS_HEADER) {
+            return Err(invalid_data(1, "Not a digram timing statistics file"));
+        }
+        let version: u32 = parse_field(1, header_fields.next())?;
+        if !(OLDEST_STATISTICS_VERSION..=STATISTICS_VERSION).contains(&version) {
+            return Err(invalid_data(1, format!("Unsupported version {version}")));
+        }
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let line_number = index + 2;
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                None => continue,
+                Some("log_length") => {
+                    hint.log_length = Some(parse_field(line_number, fields.next())?);
+                }
+                Some("last_updated") => {
+                    let since_epoch =
+                        Duration::from_micros(parse_field(line_number, fields.next())?);
+                    hint.last_updated = Some(UNIX_EPOCH + since_epoch);
+                }
+                Some("frequency") => {
+                    let key_code: KeyCode = parse_field(line_number, fields.next())?;
+                    let frequency = parse_field(line_number, fields.next())?;
+                    hint.frequencies.insert(key_code, frequency);
+                }
+                Some("digram") => {
+                    let first: KeyCode = parse_field(line_number, fields.next())?;
+                    let second: KeyCode = parse_field(line_number, fields.next())?;
+                    let frequency = parse_field(line_number, fields.next())?;
+                    hint.digram_frequencies.insert((first, second), frequency);
+                }
+                Some(name @ ("time_to" | "time_from")) => {
+                    let position = parse_position(line_number, &mut fields)?;
+                    let average = Duration::from_micros(parse_field(line_number, fields.next())?);
+                    // Before version 7 this is a sample count, which is the same as the weight without decay.
+                    let weight = parse_field(line_number, fields.next())?;
+                    let (averages, weights) = if name == "time_to" {
+                        (&mut hint.average_time_to, &mut hint.time_to_weights)
+                    } else {
+                        (&mut hint.average_time_from, &mut hint.time_from_weights)
+                    };
+                    averages.insert(position, average);
+                    weights.insert(position, weight);
+                }
+                Some("transition") => {
+                    let from = parse_position(line_number, &mut fields)?;
+                    let to = parse_position(line_number, &mut fields)?;
+                    let mean = Duration::from_micros(parse_field(line_number, fields.next())?);
+                    let standard_deviation =
+                        Duration::from_micros(parse_field(line_number, fields.next())?);
+                    let sample_count = parse_field(line_number, fields.next())?;
+                    let mut weight = sample_count as f64;
+                    let mut histogram = BTreeMap::new();
+                    if version >= 4 {
+                        weight = parse_field(line_number, fields.next())?;
+                        for field in fields {
+                            let (bucket, bucket_weight) =
+                                field.split_once(':').ok_or_else(|| {
+                                    invalid_data(line_number, format!("Invalid bucket '{field}'"))
+                                })?;
+                            histogram.insert(
+                                parse_field(line_number, Some(bucket))?,
+                                parse_field(line_number, Some(bucket_weight))?,
+                            );
+                        }
+                    }
+                    hint.transition_timings.insert(
+                        (from, to),
+                        TransitionTiming::from_summary(
+                            mean,
+                            standard_deviation,
+                            sample_count,
+                            weight,
+                            histogram,
+                        ),
+                    );
+                }
+                Some(other) => {
+                    return Err(invalid_data(
+                        line_number,
+                        format!("Unknown statistic '{other}'"),
+                    ))
+                }
+            }
+        }
+        Ok(hint)
+    }
+
+    /// How many times each key has been pressed.
+    pub fn frequencies(&self) -> &BTreeMap<KeyCode, usize> {
+        &self.frequencies
+    }
+
+    /// How many times each pair of keys has been pressed one after the other (within a second of each other).
+    pub fn digram_frequencies(&self) -> &BTreeMap<(KeyCode, KeyCode), usize> {
+        &self.digram_frequencies
+    }
+
+    /// How long it takes to type the position `to` straight after `from`, if that has ever been done.
+    pub fn transition_timing(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> Option<&TransitionTiming> {
+        self.transition_timings.get(&(from, to))
+    }
+
+    /// Every transition which has been timed, as (first position, second position) pairs.
+    pub fn transition_timings(&self) -> impl Iterator<Item = (Transition, &TransitionTiming)> + '_ {
+        self.transition_timings
+            .iter()
+            .map(|(&transition, timing)| (transition, timing))
+    }
+
+    /// The timings between `positions` as a matrix, indexed the same way as `positions`. The first index is the position typed
+    /// first.
+    pub fn transition_matrix(
+        &self,
+        positions: &[(usize, usize)],
+    ) -> Vec<Vec<Option<TransitionTiming>>> {
+        positions
+            .iter()
+            .map(|&from| {
+                positions
+                    .iter()
+                    .map(|&to| self.transition_timing(from, to).cloned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Estimates how long it takes to press the key at `to` after the one at `from`.
+    ///
+    /// This is the transition's median time if it has been timed. Otherwise it is made up from the average times from `from` and
+    /// to `to`, or whichever of them is known.
+    pub fn estimated_time_between(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+    ) -> Option<Duration> {
+        if let Some(timing) = self.transition_timing(from, to) {
+            return Some(timing.median());
+        }
+        match (
+            self.average_time_from.get(&from),
+            self.average_time_to.get(&to),
+        ) {
+            (Some(&time_from), Some(&time_to)) => Some((time_from + time_to) / 2),
+            (time_from, time_to) => time_from.or(time_to).copied(),
+        }
+    }
+
+    fn record_timing(&mut self, from: (usize, usize), to: (usize, usize), time: Duration) {
+        // Each average is over its own samples. Key frequencies can't be used for this, since they count key presses that
+        // weren't timed (after a pause, or as outliers) as well.
+        for (averages, weights, position) in [
+            (&mut self.average_time_to, &mut self.time_to_weights, to),
+            (
+                &mut self.average_time_from,
+                &mut self.time_from_weights,
+                from,
+            ),
+        ] {
+            let weight = weights.entry(position).or_insert(0.0);
+            *weight += 1.0;
+            averages
+                .entry(position)
+                .and_modify(|average| *average = adjust_average_duration(*average, time, *weight))
+                .or_insert(time);
+        }
+
+        self.transition_timings
+            .entry((from, to))
+            .or_default()
+            .add_sample(time);
+    }
+
+    /// Scales down the weight of everything timed so far to `kept` of what it was.
+    fn decay(&mut self, kept: f64) {
+        for weight in self
+            .time_to_weights
+            .values_mut()
+            .chain(self.time_from_weights.values_mut())
+        {
+            *weight *= kept;
+        }
+        for timing in self.transition_timings.values_mut() {
+            timing.decay(kept);
+        }
+    }
+
+    /// How much of its weight a sample keeps over `elapsed`.
+    fn kept_over(&self, elapsed: Duration) -> f64 {
+        match self.settings.half_life {
+            Some(half_life) => 0.5_f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64()),
+            None => 1.0,
+        }
+    }
+
+    /// Decays the timings up to `now`, if that is at least `DECAY_STEP` after the last time.
+    fn decay_to(&mut self, now: SystemTime) {
+        let Some(last_updated) = self.last_updated else {
+            self.last_updated = Some(now);
+            return;
+        };
+        let elapsed = now.duration_since(last_updated).unwrap_or_default();
+        if elapsed >= DECAY_STEP {
+            self.decay(self.kept_over(elapsed));
+            self.last_updated = Some(now);
+        }
+    }
+
+    /// The wall clock time that a key press instant stands for. Unless a session log is being replayed, the first key press
+    /// is taken to be happening now.
+    fn wall_time(&mut self, time: Instant) -> SystemTime {
+        let (clock_instant, clock_time) = *self.clock.get_or_insert((time, SystemTime::now()));
+        if time >= clock_instant {
+            clock_time + (time - clock_instant)
+        } else {
+            clock_time - (clock_instant - time)
+        }
+    }
+
+    fn is_outlier(&self, from: (usize, usize), to: (usize, usize), time: Duration) -> bool {
+        let Some(factor) = self.settings.outlier_factor else {
+            return false;
+        };
+        self.transition_timing(from, to).is_some_and(|timing| {
+            timing.samples() >= self.settings.min_samples_for_rejection
+                && timing.is_outlier(time, factor)
+        })
+    }
+
+    /// Adds the statistics gathered by another hint (on another machine, for example) to these ones. Whichever were updated
+    /// earlier are decayed to the time of the other ones first.
+    pub fn merge(&mut self, other: &DigramTimingHint) {
+        let mut other = other.clone();
+        match (self.last_updated, other.last_updated) {
+            (Some(last_updated), Some(other_last_updated)) => {
+                if let Ok(elapsed) = other_last_updated.duration_since(last_updated) {
+                    self.decay(self.kept_over(elapsed));
+                    self.last_updated = Some(other_last_updated);
+                } else if let Ok(elapsed) = last_updated.duration_since(other_last_updated) {
+                    other.decay(self.kept_over(elapsed));
+                }
+            }
+            (None, other_last_updated) => self.last_updated = other_last_updated,
+            (Some(_), None) => {}
+        }
+        for (&key_code, &frequency) in &other.frequencies {
+            *self.frequencies.entry(key_code).or_insert(0) += frequency;
+        }
+        for (&digram, &frequency) in &other.digram_frequencies {
+            *self.digram_frequencies.entry(digram).or_insert(0) += frequency;
+        }
+        for (averages, weights, other_averages, other_weights) in [
+            (
+                &mut self.average_time_to,
+                &mut self.time_to_weights,
+                &other.average_time_to,
+                &other.time_to_weights,
+            ),
+            (
+                &mut self.average_time_from,
+                &mut self.time_from_weights,
+                &other.average_time_from,
+                &other.time_from_weights,
+            ),
+        ] {
+            for (&position, &other_average) in other_averages {
+                let other_weight = other_weights.get(&position).copied().unwrap_or(0.0);
+                let weight = weights.entry(position).or_insert(0.0);
+                let average = averages.entry(position).or_insert(other_average);
+                *average = merge_averages(*average, *weight, other_average, other_weight);
+                *weight += other_weight;
+            }
+        }
+        for (&transition, other_timing) in &other.transition_timings {
+            self.transition_timings
+                .entry(transition)
+                .or_default()
+                .merge(other_timing);
+        }
+    }
 }
 
 impl LayoutHint for DigramTimingHint {
     fn receive_key_press(&mut self, key_code: KeyCode, time: Instant) {
-        let count = *self
-                .frequencies
-                .entry(key_code)
-                .and_modify(|frequency| *frequency += 1)
-                .or_insert(1);
-        if let Some(last_key) = self.last_key {
-            let last_time = self.last_time.unwrap();
+        let now = self.wall_time(time);
+        self.decay_to(now);
+        *self.frequencies.entry(key_code).or_insert(0) += 1;
+        if key_code.is_modifier() {
+            // Modifiers are held rather than typed, so the time until the next key says little about either of them. They
+            // break up the typing, like a pause does.
+            self.last_key = None;
+            self.last_time = None;
+            return;
+        }
+        let this_position = key_code.position();
+        if let (Some(last_key), Some(last_time)) = (self.last_key, self.last_time) {
             let time_between_keys = time.duration_since(last_time);
-            // If there was more than a second between keys, we  assume something else happened. We shouldn't count this in our stats.
-            if time_between_keys < Duration::from_secs(1) {
-                let last_count = *self.frequencies.get(&last_key).unwrap_or(&0);
-                let this_position = KeyboardLayout::QWERTY.position_of(key_code).unwrap();
-                let last_position = KeyboardLayout::QWERTY.position_of(last_key).unwrap();
-                self.average_time_to
-                    .entry(this_position)
-                    .and_modify(|average| {
-                        *average = adjust_average_duration(*average, time_between_keys, count)
-                    })
-                    .or_insert(time_between_keys);
-
-                self.average_time_from
-                    .entry(last_position)
-                    .and_modify(|average| {
-                        *average = adjust_average_duration(*average, time_between_keys, last_count)
-                    })
-                    .or_insert(time_between_keys);
+            // If there was too long between keys, we assume something else happened. We shouldn't count this in our stats.
+            if time_between_keys < self.settings.max_interval {
+                *self
+                    .digram_frequencies
+                    .entry((last_key, key_code))
+                    .or_insert(0) += 1;
+                let last_position = last_key.position();
+                if self.is_outlier(last_position, this_position, time_between_keys) {
+                    // The digram still happened, but the time says more about a hesitation than about the keys.
+                    self.rejected_samples += 1;
+                } else {
+                    self.record_timing(last_position, this_position, time_between_keys);
+                }
             }
         }
         self.last_key = Some(key_code);
@@ -78,10 +614,20 @@ impl LayoutHint for DigramTimingHint {
             .iter()
             .filter_map(|(&key, &frequency)| {
                 let mut rank = 0.0;
-                if let Some(time_to) = self.average_time_to.get(&(position.0, position.1)) {
+                // Keys pressed at the same instant (by replaying a coarse log, say) can average to no time at all, which
+                // isn't something to divide by.
+                if let Some(time_to) = self
+                    .average_time_to
+                    .get(&position)
+                    .filter(|time| !time.is_zero())
+                {
                     rank += frequency as f64 / time_to.as_secs_f64();
                 }
-                if let Some(time_from) = self.average_time_from.get(&(position.0, position.1)) {
+                if let Some(time_from) = self
+                    .average_time_from
+                    .get(&position)
+                    .filter(|time| !time.is_zero())
+                {
                     rank += frequency as f64 / time_from.as_secs_f64();
                 }
                 if rank > 0.0 {
@@ -93,3 +639,293 @@ impl LayoutHint for DigramTimingHint {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: KeyCode = KeyCode::A;
+    const S: KeyCode = KeyCode::S;
+    const D: KeyCode = KeyCode::D;
+
+    fn hint() -> DigramTimingHint {
+        DigramTimingHint::new(TimingSettings {
+            half_life: None,
+            ..TimingSettings::default()
+        })
+    }
+
+    /// Presses each key at its offset (in milliseconds) from the same starting instant.
+    fn press(hint: &mut DigramTimingHint, start: Instant, presses: &[(KeyCode, u64)]) {
+        for &(key_code, offset) in presses {
+            hint.receive_key_press(key_code, start + Duration::from_millis(offset));
+        }
+    }
+
+    #[test]
+    fn averages_are_over_their_own_samples() {
+        let mut hint = hint();
+        press(
+            &mut hint,
+            Instant::now(),
+            &[(A, 0), (S, 100), (D, 300), (S, 500)],
+        );
+
+        assert_eq!(hint.frequencies[&A], 1);
+        assert_eq!(hint.frequencies[&S], 2);
+        assert_eq!(hint.frequencies[&D], 1);
+        assert_eq!(hint.digram_frequencies[&(A, S)], 1);
+        assert_eq!(hint.digram_frequencies[&(S, D)], 1);
+        assert_eq!(hint.digram_frequencies[&(D, S)], 1);
+
+        assert_eq!(hint.time_to_weights[&S.position()], 2.0);
+        assert_eq!(hint.time_to_weights[&D.position()], 1.0);
+        assert!(!hint.time_to_weights.contains_key(&A.position()));
+        assert_eq!(
+            hint.average_time_to[&S.position()],
+            Duration::from_millis(150)
+        );
+        assert_eq!(
+            hint.average_time_to[&D.position()],
+            Duration::from_millis(200)
+        );
+
+        assert_eq!(hint.time_from_weights[&A.position()], 1.0);
+        assert_eq!(hint.time_from_weights[&S.position()], 1.0);
+        assert_eq!(hint.time_from_weights[&D.position()], 1.0);
+        assert_eq!(
+            hint.average_time_from[&A.position()],
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            hint.average_time_from[&S.position()],
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            hint.average_time_from[&D.position()],
+            Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn pauses_are_not_timed() {
+        let mut hint = hint();
+        let max_interval = hint.settings().max_interval.as_millis() as u64;
+        press(
+            &mut hint,
+            Instant::now(),
+            &[(A, 0), (S, max_interval), (D, 3 * max_interval)],
+        );
+
+        assert_eq!(hint.frequencies.len(), 3);
+        assert!(hint.digram_frequencies.is_empty());
+        assert!(hint.time_to_weights.is_empty());
+        assert!(hint.time_from_weights.is_empty());
+        assert!(hint.average_time_to.is_empty());
+        assert_eq!(hint.transition_timings().count(), 0);
+    }
+
+    #[test]
+    fn outliers_are_counted_but_not_timed() {
+        let mut hint = hint();
+        let start = Instant::now();
+        let samples = hint.settings().min_samples_for_rejection as u64;
+        // Each pair starts after a pause, so that only A to S is timed.
+        for index in 0..samples {
+            press(
+                &mut hint,
+                start,
+                &[(A, index * 2000), (S, index * 2000 + 100)],
+            );
+        }
+        assert_eq!(hint.rejected_samples(), 0);
+        assert_eq!(hint.time_to_weights[&S.position()], samples as f64);
+
+        press(
+            &mut hint,
+            start,
+            &[(A, samples * 2000), (S, samples * 2000 + 900)],
+        );
+
+        assert_eq!(hint.rejected_samples(), 1);
+        assert_eq!(hint.digram_frequencies[&(A, S)], samples as usize + 1);
+        assert_eq!(hint.time_to_weights[&S.position()], samples as f64);
+        assert_eq!(hint.time_from_weights[&A.position()], samples as f64);
+        assert_eq!(
+            hint.average_time_to[&S.position()],
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            hint.transition_timing(A.position(), S.position())
+                .unwrap()
+                .samples(),
+            samples as usize
+        );
+    }
+
+    #[test]
+    fn modifiers_break_up_digrams() {
+        let mut hint = hint();
+        press(
+            &mut hint,
+            Instant::now(),
+            &[(A, 0), (KeyCode::LeftShift, 50)],
+        );
+        assert_eq!(hint.last_key, None);
+
+        press(&mut hint, Instant::now(), &[(S, 0)]);
+
+        assert_eq!(hint.last_key, Some(S));
+        assert_eq!(hint.frequencies[&KeyCode::LeftShift], 1);
+        assert!(hint.digram_frequencies.is_empty());
+        assert!(hint.time_to_weights.is_empty());
+        assert!(hint.time_from_weights.is_empty());
+    }
+
+    #[test]
+    fn timings_decay_with_time() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let mut hint = DigramTimingHint::new(TimingSettings {
+            half_life: Some(day),
+            ..TimingSettings::default()
+        });
+        let start = Instant::now();
+        let start_time = UNIX_EPOCH + 1000 * day;
+        hint.clock = Some((start, start_time));
+        press(&mut hint, start, &[(A, 0), (S, 100), (D, 200)]);
+        assert_eq!(hint.last_updated(), Some(start_time));
+
+        // A day later, the old samples count for half as much, whether their transition is typed again or not.
+        press(&mut hint, start + day, &[(A, 0), (S, 300)]);
+        assert_eq!(hint.last_updated(), Some(start_time + day));
+        let a_to_s = hint.transition_timing(A.position(), S.position()).unwrap();
+        assert_eq!(a_to_s.samples(), 2);
+        assert!((a_to_s.weight() - 1.5).abs() < 1e-9);
+        assert!((a_to_s.mean().as_secs_f64() - 0.7 / 3.0).abs() < 1e-6);
+        let s_to_d = hint.transition_timing(S.position(), D.position()).unwrap();
+        assert!((s_to_d.weight() - 0.5).abs() < 1e-9);
+        assert!((hint.time_to_weights[&S.position()] - 1.5).abs() < 1e-9);
+        assert!((hint.time_from_weights[&S.position()] - 0.5).abs() < 1e-9);
+
+        // Key presses less than a decay step apart don't decay anything yet.
+        press(&mut hint, start + day + 2 * DECAY_STEP / 3, &[(A, 0)]);
+        assert_eq!(hint.last_updated(), Some(start_time + day));
+
+        let mut written = Vec::new();
+        hint.write_statistics(&mut written).unwrap();
+        let read = DigramTimingHint::read_statistics(written.as_slice()).unwrap();
+        assert_eq!(read.last_updated(), hint.last_updated());
+    }
+
+    #[test]
+    fn the_log_length_is_saved() {
+        let mut hint = hint();
+        press(&mut hint, Instant::now(), &[(A, 0), (S, 100)]);
+        let mut without_length = Vec::new();
+        hint.write_statistics(&mut without_length).unwrap();
+        hint.set_log_length(Some(48213));
+        let mut with_length = Vec::new();
+        hint.write_statistics(&mut with_length).unwrap();
+
+        let read = DigramTimingHint::read_statistics(with_length.as_slice()).unwrap();
+        assert_eq!(read.log_length(), Some(48213));
+        assert_eq!(read.frequencies, hint.frequencies);
+        let read = DigramTimingHint::read_statistics(without_length.as_slice()).unwrap();
+        assert_eq!(read.log_length(), None);
+    }
+
+    #[test]
+    fn transitions_are_saved() {
+        let mut hint = hint();
+        let start = Instant::now();
+        press(&mut hint, start, &[(A, 0), (S, 100), (D, 250), (S, 420)]);
+        press(&mut hint, start, &[(A, 2000), (S, 2130), (D, 2330)]);
+        let mut saved = Vec::new();
+        hint.write_statistics(&mut saved).unwrap();
+
+        let read = DigramTimingHint::read_statistics(saved.as_slice()).unwrap();
+        assert_eq!(read.frequencies, hint.frequencies);
+        assert_eq!(read.digram_frequencies, hint.digram_frequencies);
+        // Averages are saved to the microsecond.
+        let micros = |averages: &BTreeMap<(usize, usize), Duration>| -> Vec<_> {
+            averages
+                .iter()
+                .map(|(&position, average)| (position, average.as_micros()))
+                .collect()
+        };
+        assert_eq!(micros(&read.average_time_to), micros(&hint.average_time_to));
+        assert_eq!(
+            micros(&read.average_time_from),
+            micros(&hint.average_time_from)
+        );
+        assert_eq!(read.time_to_weights, hint.time_to_weights);
+        assert_eq!(read.time_from_weights, hint.time_from_weights);
+        assert_eq!(read.transition_timings().count(), 3);
+        for (transition, timing) in hint.transition_timings() {
+            let read_timing = read.transition_timing(transition.0, transition.1).unwrap();
+            assert_eq!(read_timing.samples(), timing.samples());
+            assert_eq!(read_timing.weight(), timing.weight());
+            assert_eq!(read_timing.mean(), timing.mean());
+            assert_eq!(read_timing.histogram(), timing.histogram());
+            let deviation = read_timing.standard_deviation().as_secs_f64();
+            assert!((deviation - timing.standard_deviation().as_secs_f64()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn merging_adds_up_the_weights() {
+        let mut first = hint();
+        press(
+            &mut first,
+            Instant::now(),
+            &[(A, 0), (S, 100), (A, 1200), (S, 1300)],
+        );
+        let mut second = hint();
+        press(&mut second, Instant::now(), &[(A, 0), (S, 400)]);
+
+        first.merge(&second);
+
+        assert_eq!(first.frequencies[&A], 3);
+        assert_eq!(first.digram_frequencies[&(A, S)], 3);
+        assert_eq!(first.time_to_weights[&S.position()], 3.0);
+        assert_eq!(first.time_from_weights[&A.position()], 3.0);
+        assert_eq!(
+            first.average_time_to[&S.position()],
+            Duration::from_millis(200)
+        );
+        let a_to_s = first.transition_timing(A.position(), S.position()).unwrap();
+        assert_eq!((a_to_s.samples(), a_to_s.weight()), (3, 3.0));
+        assert_eq!(a_to_s.mean(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn merging_decays_the_older_statistics() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let settings = TimingSettings {
+            half_life: Some(day),
+            ..TimingSettings::default()
+        };
+        let start = Instant::now();
+        let mut newer = DigramTimingHint::new(settings);
+        newer.clock = Some((start, UNIX_EPOCH + 1001 * day));
+        press(&mut newer, start, &[(A, 0), (S, 100)]);
+        let mut older = DigramTimingHint::new(settings);
+        older.clock = Some((start, UNIX_EPOCH + 1000 * day));
+        press(&mut older, start, &[(A, 0), (S, 400)]);
+
+        // The older sample counts for half, whichever way round they are merged.
+        let mut merged = older.clone();
+        merged.merge(&newer);
+        newer.merge(&older);
+        for hint in [merged, newer] {
+            assert_eq!(hint.last_updated(), Some(UNIX_EPOCH + 1001 * day));
+            assert_eq!(hint.time_to_weights[&S.position()], 1.5);
+            assert_eq!(
+                hint.average_time_to[&S.position()],
+                Duration::from_millis(200)
+            );
+            let a_to_s = hint.transition_timing(A.position(), S.position()).unwrap();
+            assert_eq!((a_to_s.samples(), a_to_s.weight()), (2, 1.5));
+        }
+    }
+}
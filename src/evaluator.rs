@@ -0,0 +1,713 @@
+//! Scores whole layouts, rather than single positions like `LayoutHint`s do.
+//!
+//! The score is made up of named metrics, each of which measures one property of the layout (like how often a finger has to type two keys in a row)
+//! against a set of key and digram frequencies, using a `FingerMap` to tell which finger presses each position and a
+//! `KeyboardGeometry` to tell how far apart the positions are. Each metric's value is multiplied by its weight and the results are added up,
+//! so metrics which should be kept low have negative weights. Higher totals are better.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    corpus::CorpusHint,
+    digram_timing::DigramTimingHint,
+    finger_map::{Finger, FingerMap},
+    geometry::KeyboardGeometry,
+    keyboard::{KeyCode, KeyboardLayout},
+};
+
+/// The row that the fingers rest on.
+const HOME_ROW: usize = 1;
+
+/// How much of the typing each finger should ideally do, from the left pinky to the right pinky. The thumbs aren't in it, since
+/// they only have the space bar row.
+const IDEAL_FINGER_LOAD: [f64; 8] = [0.08, 0.11, 0.15, 0.16, 0.16, 0.15, 0.11, 0.08];
+
+/// Key and digram frequencies, as gathered from typing or from a corpus.
+#[derive(Debug, Clone, Default)]
+pub struct KeyStatistics {
+    pub unigrams: BTreeMap<KeyCode, f64>,
+    pub bigrams: BTreeMap<(KeyCode, KeyCode), f64>,
+}
+
+impl KeyStatistics {
+    pub fn merge(&mut self, other: &KeyStatistics) {
+        for (&key_code, &frequency) in &other.unigrams {
+            *self.unigrams.entry(key_code).or_insert(0.0) += frequency;
+        }
+        for (&bigram, &frequency) in &other.bigrams {
+            *self.bigrams.entry(bigram).or_insert(0.0) += frequency;
+        }
+    }
+
+    /// Scales the frequencies so that the unigrams add up to 1, and so do the bigrams.
+    ///
+    /// This stops a large source of statistics from drowning out a small one when they are merged.
+    pub fn normalized(&self) -> KeyStatistics {
+        let total_unigrams = self.total_unigrams();
+        let total_bigrams = self.total_bigrams();
+        KeyStatistics {
+            unigrams: self
+                .unigrams
+                .iter()
+                .map(|(&key_code, &frequency)| (key_code, frequency / total_unigrams))
+                .collect(),
+            bigrams: self
+                .bigrams
+                .iter()
+                .map(|(&bigram, &frequency)| (bigram, frequency / total_bigrams))
+                .collect(),
+        }
+    }
+
+    /// Only the frequencies of the keys in the layout.
+    pub fn restricted_to(&self, layout: &KeyboardLayout) -> KeyStatistics {
+        let keys: Vec<KeyCode> = layout.iter().collect();
+        KeyStatistics {
+            unigrams: self
+                .unigrams
+                .iter()
+                .filter(|(key_code, _)| keys.contains(key_code))
+                .map(|(&key_code, &frequency)| (key_code, frequency))
+                .collect(),
+            bigrams: self
+                .bigrams
+                .iter()
+                .filter(|((first, second), _)| keys.contains(first) && keys.contains(second))
+                .map(|(&bigram, &frequency)| (bigram, frequency))
+                .collect(),
+        }
+    }
+
+    fn total_unigrams(&self) -> f64 {
+        self.unigrams.values().sum()
+    }
+
+    fn total_bigrams(&self) -> f64 {
+        self.bigrams.values().sum()
+    }
+}
+
+impl From<&CorpusHint> for KeyStatistics {
+    fn from(hint: &CorpusHint) -> Self {
+        Self {
+            unigrams: hint
+                .unigrams()
+                .map(|(key_code, count)| (key_code, count as f64))
+                .collect(),
+            bigrams: hint
+                .bigrams()
+                .map(|(bigram, count)| (bigram, count as f64))
+                .collect(),
+        }
+    }
+}
+
+impl From<&DigramTimingHint> for KeyStatistics {
+    fn from(hint: &DigramTimingHint) -> Self {
+        Self {
+            unigrams: hint
+                .frequencies()
+                .iter()
+                .map(|(&key_code, &count)| (key_code, count as f64))
+                .collect(),
+            bigrams: hint
+                .digram_frequencies()
+                .iter()
+                .map(|(&digram, &count)| (digram, count as f64))
+                .collect(),
+        }
+    }
+}
+
+/// A value for typing the second position straight after the first.
+pub type BigramMetric = fn((usize, usize), (usize, usize), &FingerMap, &KeyboardGeometry) -> f64;
+/// A value for typing a position.
+pub type UnigramMetric = fn((usize, usize), &FingerMap, &KeyboardGeometry) -> f64;
+/// A value for the share of the key presses that each finger does.
+pub type FingerLoadMetric = fn(&[f64; 10]) -> f64;
+
+/// How a metric is measured. Metrics are made up of values for single positions or for pairs of them, so that a swap of two keys
+/// can be scored by only going over the key presses and bigrams which involve them (see `SwapScorer`).
+#[derive(Debug, Clone, Copy)]
+pub enum MetricFunction {
+    /// The average over the bigrams of a value for the positions of their keys.
+    Bigram(BigramMetric),
+    /// The average over the key presses of a value for the position of the key.
+    Unigram(UnigramMetric),
+    /// A value for the share of the key presses that each finger does, in the order of `Finger::ALL`.
+    FingerLoads(FingerLoadMetric),
+}
+
+impl MetricFunction {
+    /// The metric's value for the whole layout. Keys in the statistics which aren't in the layout are left out, but still
+    /// count towards the totals.
+    fn value(
+        self,
+        layout: &KeyboardLayout,
+        statistics: &KeyStatistics,
+        finger_map: &FingerMap,
+        geometry: &KeyboardGeometry,
+    ) -> f64 {
+        let positions: BTreeMap<KeyCode, (usize, usize)> = layout
+            .keys()
+            .map(|(position, key_code)| (key_code, position))
+            .collect();
+        match self {
+            MetricFunction::Bigram(function) => {
+                let total = statistics.total_bigrams();
+                if total == 0.0 {
+                    return 0.0;
+                }
+                let mut value = 0.0;
+                for (&(first, second), &frequency) in &statistics.bigrams {
+                    if let (Some(&first), Some(&second)) =
+                        (positions.get(&first), positions.get(&second))
+                    {
+                        value += frequency * function(first, second, finger_map, geometry);
+                    }
+                }
+                value / total
+            }
+            MetricFunction::Unigram(function) => {
+                let total = statistics.total_unigrams();
+                if total == 0.0 {
+                    return 0.0;
+                }
+                let mut value = 0.0;
+                for (&key_code, &frequency) in &statistics.unigrams {
+                    if let Some(&position) = positions.get(&key_code) {
+                        value += frequency * function(position, finger_map, geometry);
+                    }
+                }
+                value / total
+            }
+            MetricFunction::FingerLoads(function) => {
+                let mut loads = [0.0; 10];
+                let total = statistics.total_unigrams();
+                if total > 0.0 {
+                    for (&key_code, &frequency) in &statistics.unigrams {
+                        if let Some(&position) = positions.get(&key_code) {
+                            loads[finger_map.finger_of(position).index()] += frequency / total;
+                        }
+                    }
+                }
+                function(&loads)
+            }
+        }
+    }
+}
+
+fn indicator(condition: bool) -> f64 {
+    if condition {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Bigrams typed with the same finger, not counting a key being pressed twice.
+pub fn same_finger_bigrams(
+    first: (usize, usize),
+    second: (usize, usize),
+    finger_map: &FingerMap,
+    _geometry: &KeyboardGeometry,
+) -> f64 {
+    indicator(first != second && finger_map.finger_of(first) == finger_map.finger_of(second))
+}
+
+/// Bigrams on one hand which skip over a row, like going between the top and bottom letter rows. Rows are counted as they
+/// are on the keyboard (see `KeyCode::physical_row`), so the number row is above the top letter row.
+pub fn row_jumps(
+    first: (usize, usize),
+    second: (usize, usize),
+    finger_map: &FingerMap,
+    _geometry: &KeyboardGeometry,
+) -> f64 {
+    indicator(
+        finger_map.hand_of(first) == finger_map.hand_of(second)
+            && KeyCode::physical_row(first).abs_diff(KeyCode::physical_row(second)) >= 2,
+    )
+}
+
+/// Bigrams which are typed by different hands.
+pub fn hand_alternation(
+    first: (usize, usize),
+    second: (usize, usize),
+    finger_map: &FingerMap,
+    _geometry: &KeyboardGeometry,
+) -> f64 {
+    indicator(finger_map.hand_of(first) != finger_map.hand_of(second))
+}
+
+/// Bigrams typed by two different fingers on the same hand.
+pub fn rolls(
+    first: (usize, usize),
+    second: (usize, usize),
+    finger_map: &FingerMap,
+    _geometry: &KeyboardGeometry,
+) -> f64 {
+    let (first, second) = (finger_map.finger_of(first), finger_map.finger_of(second));
+    indicator(first.hand() == second.hand() && first != second)
+}
+
+/// Key presses on the home row.
+pub fn home_row_usage(
+    position: (usize, usize),
+    _finger_map: &FingerMap,
+    _geometry: &KeyboardGeometry,
+) -> f64 {
+    indicator(position.0 == HOME_ROW)
+}
+
+/// How far the load on the fingers is from the ideal. This is 0 when it is exactly right and 1 when no finger does what it should.
+///
+/// The thumbs are left out, and the other fingers' loads are taken as shares of the key presses that they do.
+pub fn finger_load(loads: &[f64; 10]) -> f64 {
+    let loads: Vec<f64> = Finger::ALL
+        .into_iter()
+        .filter(|finger| !finger.is_thumb())
+        .map(|finger| loads[finger.index()])
+        .collect();
+    let total: f64 = loads.iter().sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    loads
+        .iter()
+        .zip(IDEAL_FINGER_LOAD)
+        .map(|(load, ideal)| (load / total - ideal).abs())
+        .sum::<f64>()
+        / 2.0
+}
+
+/// How far (in key widths) fingers move from where they rest on the home row, on average per key press.
+pub fn finger_travel(
+    position: (usize, usize),
+    finger_map: &FingerMap,
+    geometry: &KeyboardGeometry,
+) -> f64 {
+    let home_position = finger_map.finger_of(position).home_position();
+    geometry.distance(home_position, position).unwrap_or(0.0)
+}
+
+/// How far (in key widths) a finger moves between the two keys of a same finger bigram, on average per bigram.
+///
+/// Unlike `same_finger_bigrams`, this counts a bigram that jumps over the home row for more than one between neighbouring keys.
+pub fn same_finger_distance(
+    first: (usize, usize),
+    second: (usize, usize),
+    finger_map: &FingerMap,
+    geometry: &KeyboardGeometry,
+) -> f64 {
+    if finger_map.finger_of(first) == finger_map.finger_of(second) {
+        geometry.distance(first, second).unwrap_or(0.0)
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub name: &'static str,
+    pub weight: f64,
+    pub function: MetricFunction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricScore {
+    pub name: &'static str,
+    pub value: f64,
+    pub weight: f64,
+}
+
+impl MetricScore {
+    /// What this metric adds to the total.
+    pub fn contribution(&self) -> f64 {
+        self.value * self.weight
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub metrics: Vec<MetricScore>,
+    pub total: f64,
+}
+
+pub struct LayoutEvaluator {
+    metrics: Vec<Metric>,
+    finger_map: FingerMap,
+    geometry: KeyboardGeometry,
+}
+
+impl Default for LayoutEvaluator {
+    fn default() -> Self {
+        Self::new(vec![
+            Metric {
+                name: "same_finger_bigrams",
+                weight: -3.0,
+                function: MetricFunction::Bigram(same_finger_bigrams),
+            },
+            Metric {
+                name: "row_jumps",
+                weight: -1.0,
+                function: MetricFunction::Bigram(row_jumps),
+            },
+            Metric {
+                name: "hand_alternation",
+                weight: 1.0,
+                function: MetricFunction::Bigram(hand_alternation),
+            },
+            Metric {
+                name: "rolls",
+                weight: 0.5,
+                function: MetricFunction::Bigram(rolls),
+            },
+            Metric {
+                name: "home_row_usage",
+                weight: 1.0,
+                function: MetricFunction::Unigram(home_row_usage),
+            },
+            Metric {
+                name: "finger_load",
+                weight: -1.0,
+                function: MetricFunction::FingerLoads(finger_load),
+            },
+            Metric {
+                name: "finger_travel",
+                weight: -0.5,
+                function: MetricFunction::Unigram(finger_travel),
+            },
+            Metric {
+                name: "same_finger_distance",
+                weight: -1.0,
+                function: MetricFunction::Bigram(same_finger_distance),
+            },
+        ])
+    }
+}
+
+impl LayoutEvaluator {
+    pub fn new(metrics: Vec<Metric>) -> Self {
+        Self {
+            metrics,
+            finger_map: FingerMap::default(),
+            geometry: KeyboardGeometry::default(),
+        }
+    }
+
+    pub fn finger_map(&self) -> &FingerMap {
+        &self.finger_map
+    }
+
+    pub fn set_finger_map(&mut self, finger_map: FingerMap) {
+        self.finger_map = finger_map;
+    }
+
+    pub fn geometry(&self) -> &KeyboardGeometry {
+        &self.geometry
+    }
+
+    pub fn set_geometry(&mut self, geometry: KeyboardGeometry) {
+        self.geometry = geometry;
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    /// Changes the weight of the metric with the given name. Returns false if there is no such metric.
+    pub fn set_weight(&mut self, name: &str, weight: f64) -> bool {
+        match self.metrics.iter_mut().find(|metric| metric.name == name) {
+            Some(metric) => {
+                metric.weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Keys which aren't in the layout (like Space, when only the letters are being arranged) are left out, so each metric is
+    /// measured over the typing that the layout covers.
+    pub fn evaluate(&self, layout: &KeyboardLayout, statistics: &KeyStatistics) -> Evaluation {
+        let statistics = &statistics.restricted_to(layout);
+        let metrics: Vec<MetricScore> = self
+            .metrics
+            .iter()
+            .map(|metric| MetricScore {
+                name: metric.name,
+                value: metric
+                    .function
+                    .value(layout, statistics, &self.finger_map, &self.geometry),
+                weight: metric.weight,
+            })
+            .collect();
+        let total = metrics.iter().map(MetricScore::contribution).sum();
+        Evaluation { metrics, total }
+    }
+
+    /// Just the total from `evaluate`.
+    pub fn score(&self, layout: &KeyboardLayout, statistics: &KeyStatistics) -> f64 {
+        let statistics = &statistics.restricted_to(layout);
+        self.metrics
+            .iter()
+            .map(|metric| {
+                metric
+                    .function
+                    .value(layout, statistics, &self.finger_map, &self.geometry)
+                    * metric.weight
+            })
+            .sum()
+    }
+
+    /// Starts scoring `layout` a swap at a time. The statistics are restricted to the layout's keys, as in `evaluate`.
+    pub fn swap_scorer(&self, layout: &KeyboardLayout, statistics: &KeyStatistics) -> SwapScorer {
+        SwapScorer::new(self, layout, &statistics.restricted_to(layout))
+    }
+}
+
+/// The score of a layout, kept up to date as its keys are swapped.
+///
+/// The keys and positions are plain indices, and the metrics are worked out for every position and pair of positions up
+/// front, so a swap only has to go over the key presses and bigrams of the two keys it moves.
+pub struct SwapScorer {
+    /// The index of each position of the layout.
+    position_indices: BTreeMap<(usize, usize), usize>,
+    /// The key at each position, as indices into `unigrams`.
+    keys: Vec<usize>,
+    /// The position of each key.
+    positions: Vec<usize>,
+    /// The share of the key presses that each key makes up.
+    unigrams: Vec<f64>,
+    /// The bigrams as pairs of keys, with the share of the bigrams that they make up.
+    bigrams: Vec<(usize, usize, f64)>,
+    /// The indices into `bigrams` of the ones which each key is in.
+    key_bigrams: Vec<Vec<usize>>,
+    /// The weighted values of the bigram metrics for each pair of positions.
+    bigram_values: Vec<Vec<f64>>,
+    /// The weighted values of the unigram metrics for each position.
+    unigram_values: Vec<f64>,
+    /// The index in `Finger::ALL` of the finger which types each position.
+    fingers: Vec<usize>,
+    /// The finger load metrics with their weights.
+    load_metrics: Vec<(FingerLoadMetric, f64)>,
+    finger_loads: [f64; 10],
+    score: f64,
+}
+
+impl SwapScorer {
+    fn new(
+        evaluator: &LayoutEvaluator,
+        layout: &KeyboardLayout,
+        statistics: &KeyStatistics,
+    ) -> Self {
+        let layout_positions: Vec<(usize, usize)> = layout.positions().collect();
+        let key_codes: Vec<KeyCode> = layout.iter().collect();
+        let key_indices: BTreeMap<KeyCode, usize> = key_codes
+            .iter()
+            .enumerate()
+            .map(|(index, &key_code)| (key_code, index))
+            .collect();
+        let total_unigrams = statistics.total_unigrams();
+        let unigrams = key_codes
+            .iter()
+            .map(|key_code| match statistics.unigrams.get(key_code) {
+                Some(frequency) if total_unigrams > 0.0 => frequency / total_unigrams,
+                _ => 0.0,
+            })
+            .collect();
+        let total_bigrams = statistics.total_bigrams();
+        let mut bigrams = Vec::new();
+        let mut key_bigrams = vec![Vec::new(); key_codes.len()];
+        for (&(first, second), &frequency) in &statistics.bigrams {
+            if let (Some(&first), Some(&second)) =
+                (key_indices.get(&first), key_indices.get(&second))
+            {
+                key_bigrams[first].push(bigrams.len());
+                if second != first {
+                    key_bigrams[second].push(bigrams.len());
+                }
+                bigrams.push((first, second, frequency / total_bigrams));
+            }
+        }
+        let (finger_map, geometry) = (&evaluator.finger_map, &evaluator.geometry);
+        let mut bigram_values = vec![vec![0.0; layout_positions.len()]; layout_positions.len()];
+        let mut unigram_values = vec![0.0; layout_positions.len()];
+        let mut load_metrics = Vec::new();
+        for metric in &evaluator.metrics {
+            match metric.function {
+                MetricFunction::Bigram(function) => {
+                    for (first, row) in layout_positions.iter().zip(&mut bigram_values) {
+                        for (second, value) in layout_positions.iter().zip(row) {
+                            *value +=
+                                metric.weight * function(*first, *second, finger_map, geometry);
+                        }
+                    }
+                }
+                MetricFunction::Unigram(function) => {
+                    for (position, value) in layout_positions.iter().zip(&mut unigram_values) {
+                        *value += metric.weight * function(*position, finger_map, geometry);
+                    }
+                }
+                MetricFunction::FingerLoads(function) => {
+                    load_metrics.push((function, metric.weight))
+                }
+            }
+        }
+        let mut scorer = Self {
+            position_indices: layout_positions
+                .iter()
+                .enumerate()
+                .map(|(index, &position)| (position, index))
+                .collect(),
+            keys: (0..key_codes.len()).collect(),
+            positions: (0..key_codes.len()).collect(),
+            unigrams,
+            bigrams,
+            key_bigrams,
+            bigram_values,
+            unigram_values,
+            fingers: layout_positions
+                .iter()
+                .map(|&position| finger_map.finger_of(position).index())
+                .collect(),
+            load_metrics,
+            finger_loads: [0.0; 10],
+            score: 0.0,
+        };
+        for (key, &share) in scorer.unigrams.iter().enumerate() {
+            scorer.finger_loads[scorer.fingers[key]] += share;
+            scorer.score += share * scorer.unigram_values[key];
+        }
+        for &(first, second, share) in &scorer.bigrams {
+            scorer.score += share * scorer.bigram_values[first][second];
+        }
+        scorer.score += scorer.load_score(&scorer.finger_loads);
+        scorer
+    }
+
+    fn load_score(&self, loads: &[f64; 10]) -> f64 {
+        self.load_metrics
+            .iter()
+            .map(|(function, weight)| weight * function(loads))
+            .sum()
+    }
+
+    /// The score of the layout with the swaps so far, the same as `LayoutEvaluator::score` would give.
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    /// How much swapping the keys at the two positions would change the score.
+    pub fn swap_delta(&self, first: (usize, usize), second: (usize, usize)) -> f64 {
+        let (first, second) = (
+            self.position_indices[&first],
+            self.position_indices[&second],
+        );
+        let (first_key, second_key) = (self.keys[first], self.keys[second]);
+        let moved = |key: usize| {
+            if key == first_key {
+                second
+            } else if key == second_key {
+                first
+            } else {
+                self.positions[key]
+            }
+        };
+        let mut delta = 0.0;
+        // The bigrams between the two keys are in both lists, so they are only counted from the first one.
+        let second_key_bigrams = self.key_bigrams[second_key].iter().filter(|&&bigram| {
+            let (from, to, _) = self.bigrams[bigram];
+            from != first_key && to != first_key
+        });
+        for &bigram in self.key_bigrams[first_key].iter().chain(second_key_bigrams) {
+            let (from, to, share) = self.bigrams[bigram];
+            delta += share
+                * (self.bigram_values[moved(from)][moved(to)]
+                    - self.bigram_values[self.positions[from]][self.positions[to]]);
+        }
+        let (first_share, second_share) = (self.unigrams[first_key], self.unigrams[second_key]);
+        delta += (first_share - second_share)
+            * (self.unigram_values[second] - self.unigram_values[first]);
+        if !self.load_metrics.is_empty() && self.fingers[first] != self.fingers[second] {
+            let mut loads = self.finger_loads;
+            loads[self.fingers[first]] += second_share - first_share;
+            loads[self.fingers[second]] += first_share - second_share;
+            delta += self.load_score(&loads) - self.load_score(&self.finger_loads);
+        }
+        delta
+    }
+
+    /// Swaps the keys at the two positions.
+    pub fn swap(&mut self, first: (usize, usize), second: (usize, usize)) {
+        self.score += self.swap_delta(first, second);
+        let (first, second) = (
+            self.position_indices[&first],
+            self.position_indices[&second],
+        );
+        let (first_key, second_key) = (self.keys[first], self.keys[second]);
+        let (first_share, second_share) = (self.unigrams[first_key], self.unigrams[second_key]);
+        self.finger_loads[self.fingers[first]] += second_share - first_share;
+        self.finger_loads[self.fingers[second]] += first_share - second_share;
+        self.keys.swap(first, second);
+        self.positions.swap(first_key, second_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_jump_share(first: KeyCode, second: KeyCode) -> f64 {
+        row_jumps(
+            first.position(),
+            second.position(),
+            &FingerMap::STANDARD,
+            &KeyboardGeometry::ansi(),
+        )
+    }
+
+    #[test]
+    fn row_jumps_go_by_the_rows_on_the_keyboard() {
+        assert_eq!(row_jump_share(KeyCode::Q, KeyCode::Z), 1.0);
+        assert_eq!(row_jump_share(KeyCode::Digit3, KeyCode::D), 1.0);
+        // The number row is right above the top letter row, not below the bottom one.
+        assert_eq!(row_jump_share(KeyCode::Digit3, KeyCode::E), 0.0);
+        assert_eq!(row_jump_share(KeyCode::Digit2, KeyCode::X), 1.0);
+        assert_eq!(row_jump_share(KeyCode::Q, KeyCode::A), 0.0);
+    }
+
+    #[test]
+    fn swaps_change_the_score_by_their_delta() {
+        let layout = KeyboardLayout::qwerty_main_block();
+        let mut statistics = KeyStatistics::default();
+        for (index, key_code) in KeyCode::ALL.into_iter().enumerate() {
+            statistics.unigrams.insert(key_code, (index % 7 + 1) as f64);
+            for (other_index, other) in KeyCode::ALL.into_iter().enumerate() {
+                if (index * 31 + other_index * 17) % 11 == 0 {
+                    statistics
+                        .bigrams
+                        .insert((key_code, other), (index % 5 + 1) as f64);
+                }
+            }
+        }
+        let evaluator = LayoutEvaluator::default();
+        let mut scorer = evaluator.swap_scorer(&layout, &statistics);
+        let mut swapped = layout.clone();
+        assert!((scorer.score() - evaluator.score(&layout, &statistics)).abs() < 1e-9);
+        for (first, second) in [
+            (KeyCode::A, KeyCode::Semicolon),
+            (KeyCode::E, KeyCode::E),
+            (KeyCode::Space, KeyCode::Q),
+            (KeyCode::F, KeyCode::J),
+            (KeyCode::LeftShift, KeyCode::Digit1),
+        ] {
+            let (first, second) = (first.position(), second.position());
+            let before = evaluator.score(&swapped, &statistics);
+            swapped.swap(first, second);
+            let after = evaluator.score(&swapped, &statistics);
+            assert!((scorer.swap_delta(first, second) - (after - before)).abs() < 1e-9);
+            scorer.swap(first, second);
+            assert!((scorer.score() - after).abs() < 1e-9);
+        }
+    }
+}
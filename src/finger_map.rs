@@ -0,0 +1,168 @@
+//! Which finger (and so which hand) presses each key position.
+//!
+//! The standard map is the usual touch typing one, where each finger has its own column and the index fingers also cover
+//! the two middle columns. The angle mod shifts the left hand's bottom row over by one finger, which keeps the left wrist
+//! straighter on row-staggered keyboards. Both maps leave the space bar row to the thumbs.
+
+use crate::keyboard::KeyCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Finger {
+    LeftPinky,
+    LeftRing,
+    LeftMiddle,
+    LeftIndex,
+    LeftThumb,
+    RightThumb,
+    RightIndex,
+    RightMiddle,
+    RightRing,
+    RightPinky,
+}
+
+impl Finger {
+    /// Every finger, from the left pinky to the right pinky.
+    pub const ALL: [Finger; 10] = [
+        Finger::LeftPinky,
+        Finger::LeftRing,
+        Finger::LeftMiddle,
+        Finger::LeftIndex,
+        Finger::LeftThumb,
+        Finger::RightThumb,
+        Finger::RightIndex,
+        Finger::RightMiddle,
+        Finger::RightRing,
+        Finger::RightPinky,
+    ];
+
+    /// The finger's place in `ALL`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Where the finger rests on the home row. Both thumbs rest on the space bar.
+    pub fn home_position(self) -> (usize, usize) {
+        if self.is_thumb() {
+            return KeyCode::Space.position();
+        }
+        (1, [0, 1, 2, 3, 0, 0, 6, 7, 8, 9][self.index()])
+    }
+
+    pub fn is_thumb(self) -> bool {
+        matches!(self, Finger::LeftThumb | Finger::RightThumb)
+    }
+
+    pub fn hand(self) -> Hand {
+        if self.index() < 5 {
+            Hand::Left
+        } else {
+            Hand::Right
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FingerMap {
+    fingers: [[Finger; 10]; 3],
+}
+
+impl Default for FingerMap {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+const STANDARD_ROW: [Finger; 10] = [
+    Finger::LeftPinky,
+    Finger::LeftRing,
+    Finger::LeftMiddle,
+    Finger::LeftIndex,
+    Finger::LeftIndex,
+    Finger::RightIndex,
+    Finger::RightIndex,
+    Finger::RightMiddle,
+    Finger::RightRing,
+    Finger::RightPinky,
+];
+
+impl FingerMap {
+    pub const STANDARD: FingerMap = FingerMap {
+        fingers: [STANDARD_ROW, STANDARD_ROW, STANDARD_ROW],
+    };
+
+    pub const ANGLE_MOD: FingerMap = FingerMap {
+        fingers: [
+            STANDARD_ROW,
+            STANDARD_ROW,
+            [
+                Finger::LeftRing,
+                Finger::LeftMiddle,
+                Finger::LeftIndex,
+                Finger::LeftIndex,
+                Finger::LeftIndex,
+                Finger::RightIndex,
+                Finger::RightIndex,
+                Finger::RightMiddle,
+                Finger::RightRing,
+                Finger::RightPinky,
+            ],
+        ],
+    };
+
+    /// Creates a map from the finger for each letter key position, top row first.
+    pub fn new(fingers: [[Finger; 10]; 3]) -> Self {
+        Self { fingers }
+    }
+
+    /// Positions outside the three letter rows are pressed by the finger of the standard map's column, and columns past the
+    /// tenth by the right pinky. The keys left of the letters (row 5 of `KeyboardLayout::qwerty_main_block`) are the left
+    /// pinky's. On the space bar row (row 4), the pinkies have the Control keys at the ends and the thumbs have the rest, split
+    /// after the space bar.
+    pub fn finger_of(&self, position: (usize, usize)) -> Finger {
+        let (row, column) = position;
+        match self.fingers.get(row) {
+            Some(fingers) => fingers[column.min(9)],
+            None if row == 5 => Finger::LeftPinky,
+            None if row == 4 => {
+                let space_column = KeyCode::Space.position().1;
+                match column {
+                    0 => Finger::LeftPinky,
+                    column if column <= space_column => Finger::LeftThumb,
+                    column if column < KeyCode::RightControl.position().1 => Finger::RightThumb,
+                    _ => Finger::RightPinky,
+                }
+            }
+            None => STANDARD_ROW[column.min(9)],
+        }
+    }
+
+    pub fn hand_of(&self, position: (usize, usize)) -> Hand {
+        self.finger_of(position).hand()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_space_bar_row_is_for_the_thumbs() {
+        let finger_map = FingerMap::STANDARD;
+        let finger_of = |key_code: KeyCode| finger_map.finger_of(key_code.position());
+        assert_eq!(finger_of(KeyCode::LeftControl), Finger::LeftPinky);
+        assert_eq!(finger_of(KeyCode::LeftAlt), Finger::LeftThumb);
+        assert_eq!(finger_of(KeyCode::Space), Finger::LeftThumb);
+        assert_eq!(finger_of(KeyCode::RightAlt), Finger::RightThumb);
+        assert_eq!(finger_of(KeyCode::RightMeta), Finger::RightThumb);
+        assert_eq!(finger_of(KeyCode::RightControl), Finger::RightPinky);
+        assert_eq!(Finger::LeftThumb.hand(), Hand::Left);
+        assert_eq!(Finger::RightThumb.hand(), Hand::Right);
+        assert_eq!(Finger::RightIndex.home_position(), (1, 6));
+    }
+}
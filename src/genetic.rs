@@ -0,0 +1,213 @@
+//! A genetic algorithm, which evolves a population of layouts instead of improving a single one.
+//!
+//! Each generation, parents are picked by tournament selection and combined with order crossover, which keeps a run of
+//! positions from one parent and fills in the rest of the keys in the order they come in the other. The children are then
+//! mutated by swapping keys. Since the whole population is kept, the search can end with a shortlist of good layouts to pick
+//! from rather than a single answer.
+
+use crate::{keyboard::KeyboardLayout, random::Random};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneticSearch {
+    pub population_size: usize,
+    pub generations: usize,
+    /// How many layouts take part in each tournament. Bigger tournaments favour the fittest layouts more strongly.
+    pub tournament_size: usize,
+    /// The chance of a child having two of its keys swapped. It is applied again after each swap, so more than one can happen,
+    /// up to one swap per position.
+    pub mutation_rate: f64,
+    /// How many of the best layouts are carried over to the next generation unchanged.
+    pub elite_count: usize,
+    pub seed: u64,
+}
+
+impl Default for GeneticSearch {
+    fn default() -> Self {
+        Self {
+            population_size: 100,
+            generations: 200,
+            tournament_size: 3,
+            mutation_rate: 0.3,
+            elite_count: 2,
+            seed: 0,
+        }
+    }
+}
+
+/// A layout along with its fitness.
+#[derive(Debug, Clone)]
+pub struct ScoredLayout {
+    pub layout: KeyboardLayout,
+    pub fitness: f64,
+}
+
+/// The population after the last generation, from the fittest layout down.
+#[derive(Debug, Clone)]
+pub struct Population(Vec<ScoredLayout>);
+
+impl Population {
+    pub fn best(&self) -> &ScoredLayout {
+        &self.0[0]
+    }
+
+    /// The fittest `count` layouts, without any duplicates.
+    pub fn shortlist(&self, count: usize) -> Vec<ScoredLayout> {
+        let mut shortlist: Vec<ScoredLayout> = Vec::new();
+        for scored_layout in &self.0 {
+            if shortlist.len() == count {
+                break;
+            }
+            if !shortlist
+                .iter()
+                .any(|other| other.layout == scored_layout.layout)
+            {
+                shortlist.push(scored_layout.clone());
+            }
+        }
+        shortlist
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ScoredLayout> {
+        self.0.iter()
+    }
+}
+
+/// Keeps the keys between `start` and `end` (indices into `positions`) where they are in the first parent, and places the rest in
+/// the order they appear in the second parent, starting after `end` and wrapping around.
+fn order_crossover(
+    first_parent: &KeyboardLayout,
+    second_parent: &KeyboardLayout,
+    positions: &[(usize, usize)],
+    start: usize,
+    end: usize,
+) -> KeyboardLayout {
+    let mut child = first_parent.clone();
+    let kept: Vec<_> = positions[start..end]
+        .iter()
+        .map(|&position| first_parent.key_at(position))
+        .collect();
+    let mut remaining = positions[end..]
+        .iter()
+        .chain(&positions[..end])
+        .map(|&position| second_parent.key_at(position))
+        .filter(|key_code| !kept.contains(key_code));
+    for &(row, column) in positions[end..].iter().chain(&positions[..start]) {
+        if let Some(key_code) = remaining.next().flatten() {
+            child.set_key_at(row, column, key_code);
+        }
+    }
+    child
+}
+
+fn random_swap(layout: &mut KeyboardLayout, positions: &[(usize, usize)], random: &mut Random) {
+    if positions.len() < 2 {
+        return;
+    }
+    let first = random.below(positions.len());
+    let second = (first + 1 + random.below(positions.len() - 1)) % positions.len();
+    layout.swap(positions[first], positions[second]);
+}
+
+impl GeneticSearch {
+    /// Evolves a population which starts off as `start` and random shuffles of it. Higher fitness is better.
+    pub fn evolve(
+        &self,
+        start: KeyboardLayout,
+        fitness: impl Fn(&KeyboardLayout) -> f64,
+    ) -> Population {
+        let mut random = Random::new(self.seed);
+        let positions: Vec<_> = start.positions().collect();
+        let population_size = self.population_size.max(1);
+        let score = |layout: KeyboardLayout| ScoredLayout {
+            fitness: fitness(&layout),
+            layout,
+        };
+        let mut population = vec![score(start.clone())];
+        while population.len() < population_size {
+            let mut layout = start.clone();
+            for _ in 0..positions.len() {
+                random_swap(&mut layout, &positions, &mut random);
+            }
+            population.push(score(layout));
+        }
+        sort_by_fitness(&mut population);
+
+        // With fewer than two positions, there is nothing to rearrange.
+        let generations = if positions.len() < 2 {
+            0
+        } else {
+            self.generations
+        };
+        for _ in 0..generations {
+            let mut next_generation: Vec<ScoredLayout> = population
+                .iter()
+                .take(self.elite_count.min(population_size))
+                .cloned()
+                .collect();
+            while next_generation.len() < population_size {
+                let first_parent = self.tournament(&population, &mut random);
+                let second_parent = self.tournament(&population, &mut random);
+                let start = random.below(positions.len());
+                let end = start + 1 + random.below(positions.len() - start);
+                let mut child =
+                    order_crossover(first_parent, second_parent, &positions, start, end);
+                // A rate of 1 or more would otherwise keep swapping forever.
+                for _ in 0..positions.len() {
+                    if random.fraction() >= self.mutation_rate {
+                        break;
+                    }
+                    random_swap(&mut child, &positions, &mut random);
+                }
+                next_generation.push(score(child));
+            }
+            population = next_generation;
+            sort_by_fitness(&mut population);
+        }
+        Population(population)
+    }
+
+    /// Picks the fittest of a few random layouts.
+    fn tournament<'a>(
+        &self,
+        population: &'a [ScoredLayout],
+        random: &mut Random,
+    ) -> &'a KeyboardLayout {
+        let mut winner = &population[random.below(population.len())];
+        for _ in 1..self.tournament_size {
+            let contender = &population[random.below(population.len())];
+            if contender.fitness > winner.fitness {
+                winner = contender;
+            }
+        }
+        &winner.layout
+    }
+}
+
+fn sort_by_fitness(population: &mut [ScoredLayout]) {
+    population.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_mutation_rate_of_one_still_finishes() {
+        let search = GeneticSearch {
+            population_size: 10,
+            generations: 5,
+            mutation_rate: 1.0,
+            ..GeneticSearch::default()
+        };
+        let start = KeyboardLayout::qwerty();
+        let population = search.evolve(start.clone(), |layout| {
+            layout
+                .keys()
+                .filter(|&(position, key)| start.key_at(position) == Some(key))
+                .count() as f64
+        });
+        assert_eq!(population.iter().count(), 10);
+        // The elite keeps the starting layout, which has every key in place.
+        assert_eq!(population.best().fitness, start.len() as f64);
+    }
+}
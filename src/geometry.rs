@@ -0,0 +1,109 @@
+//! Where each key physically is on the keyboard, for drawing it and for working out how far fingers have to move.
+//!
+//! Coordinates and sizes are in key units, where 1 is the width of a normal letter key (19.05 mm on most keyboards). x grows
+//! to the right and y grows downwards, and each key is given by its top left corner.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyGeometry {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl KeyGeometry {
+    /// A normal 1x1 key.
+    pub fn new(x: f64, y: f64) -> Self {
+        Self {
+            x,
+            y,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardGeometry {
+    keys: BTreeMap<(usize, usize), KeyGeometry>,
+}
+
+impl Default for KeyboardGeometry {
+    fn default() -> Self {
+        Self::ansi()
+    }
+}
+
+impl KeyboardGeometry {
+    pub fn new(keys: BTreeMap<(usize, usize), KeyGeometry>) -> Self {
+        Self { keys }
+    }
+
+    /// Builds a 3x10 grid where each row is shifted right by `row_offsets` and each column is shifted down by `column_offsets`.
+    /// `hand_gap` is the extra space between the fifth and sixth columns.
+    fn grid(row_offsets: [f64; 3], column_offsets: [f64; 10], hand_gap: f64) -> Self {
+        let mut keys = BTreeMap::new();
+        for (row, row_offset) in row_offsets.into_iter().enumerate() {
+            for (column, column_offset) in column_offsets.into_iter().enumerate() {
+                let gap = if column >= 5 { hand_gap } else { 0.0 };
+                keys.insert(
+                    (row, column),
+                    KeyGeometry::new(row_offset + column as f64 + gap, row as f64 + column_offset),
+                );
+            }
+        }
+        Self::new(keys)
+    }
+
+    /// A normal row-staggered keyboard, where the home row is a quarter of a key to the right of the top row and the bottom row
+    /// is half a key further.
+    pub fn ansi() -> Self {
+        Self::grid([0.0, 0.25, 0.75], [0.0; 10], 0.0)
+    }
+
+    /// Keys in a straight grid, without any stagger.
+    pub fn ortholinear() -> Self {
+        Self::grid([0.0; 3], [0.0; 10], 0.0)
+    }
+
+    /// A split keyboard with the columns staggered to follow the lengths of the fingers: the middle finger's column is the
+    /// highest and the pinky's is the lowest.
+    pub fn column_staggered_split() -> Self {
+        Self::grid(
+            [0.0; 3],
+            [0.5, 0.25, 0.0, 0.125, 0.25, 0.25, 0.125, 0.0, 0.25, 0.5],
+            1.5,
+        )
+    }
+
+    pub fn key(&self, position: (usize, usize)) -> Option<&KeyGeometry> {
+        self.keys.get(&position)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = ((usize, usize), &KeyGeometry)> {
+        self.keys.iter().map(|(&position, key)| (position, key))
+    }
+
+    /// The width and height of the smallest box (starting at 0, 0) which holds every key.
+    pub fn size(&self) -> (f64, f64) {
+        self.keys.values().fold((0.0, 0.0), |(width, height), key| {
+            (
+                f64::max(width, key.x + key.width),
+                f64::max(height, key.y + key.height),
+            )
+        })
+    }
+
+    /// The distance between the centers of two keys, or `None` if either of them isn't on the keyboard.
+    pub fn distance(&self, first: (usize, usize), second: (usize, usize)) -> Option<f64> {
+        let (first_x, first_y) = self.key(first)?.center();
+        let (second_x, second_y) = self.key(second)?.center();
+        Some((first_x - second_x).hypot(first_y - second_y))
+    }
+}
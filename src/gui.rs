@@ -1,17 +1,32 @@
-use std::error::Error;
+use std::{collections::BTreeSet, error::Error};
 
 use eframe::egui::{self, Align, Button, Direction, Vec2};
 use eframe::egui::{Layout, Pos2, Rect, Ui};
 use eframe::NativeOptions;
 
-use crate::keyboard::KeyboardLayout;
+use crate::{
+    geometry::KeyboardGeometry,
+    keyboard::{KeyCode, KeyboardLayout, Keystroke},
+    layers::LayeredLayout,
+    layout_file::LayoutFile,
+    presets::PRESETS,
+};
 
 struct KeyboardLayoutOptimizerGui {
-    create_layout: Box<dyn FnMut() -> KeyboardLayout>,
-    enable_layout: Box<dyn FnMut(&KeyboardLayout)>,
+    /// Creates a layout of the template's keys, at its positions.
+    create_layout: Box<dyn FnMut(&KeyboardLayout) -> LayeredLayout>,
+    enable_layout: Box<dyn FnMut(&LayeredLayout)>,
     disable_layout: Box<dyn FnMut()>,
-    custom_keyboard_layout: Option<KeyboardLayout>,
+    custom_keyboard_layout: Option<LayeredLayout>,
     enabled: bool,
+    /// The name of the layer to show, or `None` for the base layer.
+    shown_layer: Option<String>,
+    geometry: KeyboardGeometry,
+    /// The layout file that the Save and Load buttons use.
+    layout_path: String,
+    /// What happened the last time a layout was saved or loaded.
+    message: Option<String>,
+    template: KeyboardLayout,
 }
 
 impl eframe::App for KeyboardLayoutOptimizerGui {
@@ -22,7 +37,28 @@ impl eframe::App for KeyboardLayoutOptimizerGui {
                 let enable_checkbox = ui
                     .child_ui(ui.max_rect(), Layout::right_to_left(Align::TOP))
                     .checkbox(&mut self.enabled, "Enable");
-                Self::render_keyboard(ui, layout);
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.shown_layer, None, "Base");
+                    for layer in layout.layers() {
+                        ui.selectable_value(
+                            &mut self.shown_layer,
+                            Some(layer.name().to_string()),
+                            layer.name(),
+                        );
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.layout_path);
+                    if ui.button("Save").clicked() {
+                        self.message = Some(
+                            match LayoutFile::new(layout.clone()).save(&self.layout_path) {
+                                Ok(()) => format!("Saved to {}", self.layout_path),
+                                Err(error) => format!("Failed to save the layout: {error}"),
+                            },
+                        );
+                    }
+                });
+                Self::render_keyboard(ui, layout, self.shown_layer.as_deref(), &self.geometry);
                 if enable_checkbox.changed() {
                     if self.enabled {
                         (self.enable_layout)(layout);
@@ -32,67 +68,128 @@ impl eframe::App for KeyboardLayoutOptimizerGui {
                 }
                 if back_button.clicked() {
                     self.custom_keyboard_layout = None;
+                    self.shown_layer = None;
+                    self.message = None;
                     if self.enabled {
                         (self.disable_layout)();
                         self.enabled = false;
                     }
                 }
             } else {
-                let create_button = ui.button("Create layout");
-                if create_button.clicked() {
-                    let layout = (self.create_layout)();
-                    self.custom_keyboard_layout = Some(layout);
-                }
+                ui.horizontal(|ui| {
+                    let create_button = ui.button("Create layout");
+                    ui.selectable_value(&mut self.template, KeyboardLayout::qwerty(), "Letters");
+                    ui.selectable_value(
+                        &mut self.template,
+                        KeyboardLayout::qwerty_main_block(),
+                        "Main block",
+                    );
+                    if create_button.clicked() {
+                        let layout = (self.create_layout)(&self.template);
+                        self.custom_keyboard_layout = Some(layout);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.layout_path);
+                    if ui.button("Load").clicked() {
+                        match LayoutFile::load(&self.layout_path) {
+                            Ok(file) => {
+                                self.custom_keyboard_layout = Some(file.layout);
+                                self.message = None;
+                            }
+                            Err(error) => {
+                                self.message = Some(format!("Failed to load the layout: {error}"));
+                            }
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    for preset in PRESETS {
+                        if ui.button(preset.name).clicked() {
+                            self.custom_keyboard_layout = Some(LayeredLayout::new(preset.layout()));
+                        }
+                    }
+                });
             }
-            Self::render_keyboard(ui, &KeyboardLayout::QWERTY);
+            if let Some(message) = &self.message {
+                ui.label(message);
+            }
+            Self::render_keyboard(
+                ui,
+                &LayeredLayout::new(self.template.clone()),
+                self.shown_layer.as_deref(),
+                &self.geometry,
+            );
         });
     }
 }
 
 impl KeyboardLayoutOptimizerGui {
-    fn render_keyboard(ui: &mut Ui, layout: &KeyboardLayout) {
+    fn render_keyboard(
+        ui: &mut Ui,
+        layout: &LayeredLayout,
+        layer: Option<&str>,
+        geometry: &KeyboardGeometry,
+    ) {
         let Vec2 {
             x: available_width,
             y: available_height,
         } = ui.available_size();
+        let (keyboard_width, keyboard_height) = geometry.size();
+        let (keyboard_width, keyboard_height) = (keyboard_width as f32, keyboard_height as f32);
         // The keyboard should take up at most 2/3 of the available width and 2/3 of the available height
         let key_size = f32::min(
-            2.0 / 3.0 * available_width / 11.0,
-            2.0 / 3.0 * available_height / 3.0,
+            2.0 / 3.0 * available_width / keyboard_width,
+            2.0 / 3.0 * available_height / keyboard_height,
         );
-        let min_y = available_height - key_size * 3.0;
-        let height = 3.0 * key_size;
+        let min_y = available_height - key_size * keyboard_height;
+        // A layer is shown as what the keys type while its first activation key is held.
+        let held_keys: BTreeSet<_> = layer
+            .and_then(|name| layout.layer(name))
+            .and_then(|layer| layer.activation_keys().first().copied())
+            .into_iter()
+            .collect();
+        let offset = (available_width - keyboard_width * key_size) / 2.0;
         let mut keyboard_region = ui.child_ui(
-            Rect::from_min_size(Pos2::new(0.0, min_y), Vec2::new(available_width, height)),
+            Rect::from_min_size(
+                Pos2::new(0.0, min_y),
+                Vec2::new(available_width, keyboard_height * key_size),
+            ),
             Layout::centered_and_justified(Direction::TopDown),
         );
-        // Unfortunately there is no easy way to get the keyboard to be horizontally centered
-        let offset = (available_width - 11.0 * key_size) / 2.0;
-        keyboard_region.vertical(|rows| {
-            let row_offsets = [0.0, key_size / 2.0, key_size];
-            for row_index in 0..3 {
-                rows.horizontal(|row| {
-                    row.add_space(offset);
-                    row.add_space(row_offsets[row_index]);
-                    for column_index in 0..10 {
-                        row.add(
-                            Button::new(layout.key_at((row_index, column_index)).to_string())
-                                .min_size(Vec2::new(key_size, key_size)),
-                        );
-                    }
-                    row.add_space(key_size - row_offsets[row_index]);
-                    row.add_space(offset);
-                    assert_eq!(row.available_width().round(), 0.0);
-                });
-            }
-        });
+        for (position, key) in geometry.keys() {
+            let key_rect = Rect::from_min_size(
+                Pos2::new(
+                    offset + key.x as f32 * key_size,
+                    min_y + key.y as f32 * key_size,
+                ),
+                Vec2::new(key.width as f32 * key_size, key.height as f32 * key_size),
+            );
+            keyboard_region.put(
+                key_rect,
+                Button::new(
+                    layout
+                        .keystroke_at(position, &held_keys)
+                        // Keys that the layout doesn't move stay where they are on the physical keyboard.
+                        .or_else(|| {
+                            KeyCode::at_position(position)
+                                .map(|key_code| Keystroke::typed(key_code, &held_keys))
+                        })
+                        .map(|keystroke| keystroke.to_string())
+                        .unwrap_or_default(),
+                )
+                .min_size(key_rect.size()),
+            );
+        }
     }
 }
 
 pub fn launch_gui(
-    create_layout: Box<dyn FnMut() -> KeyboardLayout>,
-    enable_layout: Box<dyn FnMut(&KeyboardLayout)>,
+    create_layout: Box<dyn FnMut(&KeyboardLayout) -> LayeredLayout>,
+    enable_layout: Box<dyn FnMut(&LayeredLayout)>,
     disable_layout: Box<dyn FnMut()>,
+    geometry: KeyboardGeometry,
+    layout_path: String,
 ) -> Result<(), Box<dyn Error>> {
     let native_options = NativeOptions::default();
     eframe::run_native(
@@ -105,6 +202,11 @@ pub fn launch_gui(
                 disable_layout,
                 custom_keyboard_layout: None,
                 enabled: false,
+                shown_layer: None,
+                geometry,
+                layout_path,
+                message: None,
+                template: KeyboardLayout::qwerty(),
             })
         }),
     )?;
@@ -0,0 +1,118 @@
+//! The Hungarian (Kuhn–Munkres) algorithm, for solving assignment problems exactly.
+//!
+//! This is the usual O(n³) version with row and column potentials, which adds one row at a time and finds the shortest
+//! augmenting path to a free column for it.
+
+/// Assigns every row of a square cost matrix to a different column, so that the total cost is as low as possible.
+///
+/// Returns the column for each row. The costs have to be finite, or the search for an augmenting path never ends.
+pub fn minimum_cost_assignment(costs: &[Vec<f64>]) -> Vec<usize> {
+    debug_assert!(costs.iter().flatten().all(|cost| cost.is_finite()));
+    let size = costs.len();
+    // Index 0 stands for "no row" in `column_rows`, so rows and columns are numbered from 1 here.
+    let mut row_potentials = vec![0.0; size + 1];
+    let mut column_potentials = vec![0.0; size + 1];
+    let mut column_rows = vec![0; size + 1];
+    let mut previous_columns = vec![0; size + 1];
+    for row in 1..=size {
+        column_rows[0] = row;
+        let mut column = 0;
+        let mut minimums = vec![f64::INFINITY; size + 1];
+        let mut used = vec![false; size + 1];
+        loop {
+            used[column] = true;
+            let current_row = column_rows[column];
+            let mut delta = f64::INFINITY;
+            let mut next_column = 0;
+            for other_column in 1..=size {
+                if used[other_column] {
+                    continue;
+                }
+                let reduced_cost = costs[current_row - 1][other_column - 1]
+                    - row_potentials[current_row]
+                    - column_potentials[other_column];
+                if reduced_cost < minimums[other_column] {
+                    minimums[other_column] = reduced_cost;
+                    previous_columns[other_column] = column;
+                }
+                if minimums[other_column] < delta {
+                    delta = minimums[other_column];
+                    next_column = other_column;
+                }
+            }
+            for other_column in 0..=size {
+                if used[other_column] {
+                    row_potentials[column_rows[other_column]] += delta;
+                    column_potentials[other_column] -= delta;
+                } else {
+                    minimums[other_column] -= delta;
+                }
+            }
+            column = next_column;
+            if column_rows[column] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path back to the start.
+        while column != 0 {
+            let previous_column = previous_columns[column];
+            column_rows[column] = column_rows[previous_column];
+            column = previous_column;
+        }
+    }
+    let mut assignment = vec![0; size];
+    for column in 1..=size {
+        if column_rows[column] != 0 {
+            assignment[column_rows[column] - 1] = column - 1;
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    fn total_cost(costs: &[Vec<f64>], assignment: &[usize]) -> f64 {
+        assignment
+            .iter()
+            .enumerate()
+            .map(|(row, &column)| costs[row][column])
+            .sum()
+    }
+
+    /// The lowest total cost, found by trying every assignment.
+    fn brute_force(costs: &[Vec<f64>], row: usize, used: &mut Vec<bool>) -> f64 {
+        if row == costs.len() {
+            return 0.0;
+        }
+        let mut best = f64::INFINITY;
+        for column in 0..costs.len() {
+            if !used[column] {
+                used[column] = true;
+                best = best.min(costs[row][column] + brute_force(costs, row + 1, used));
+                used[column] = false;
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn finds_the_cheapest_assignment() {
+        let mut random = Random::new(1);
+        for size in 1..=6 {
+            for _ in 0..10 {
+                let costs: Vec<Vec<f64>> = (0..size)
+                    .map(|_| (0..size).map(|_| random.fraction() * 10.0 - 5.0).collect())
+                    .collect();
+                let assignment = minimum_cost_assignment(&costs);
+                let mut columns = assignment.clone();
+                columns.sort();
+                assert_eq!(columns, (0..size).collect::<Vec<_>>());
+                let expected = brute_force(&costs, 0, &mut vec![false; size]);
+                assert!((total_cost(&costs, &assignment) - expected).abs() < 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! A small JSON parser, just big enough for the files we read.
+//!
+//! It also accepts the looser syntax that keyboard-layout-editor.com uses for its raw data: object keys without quotes,
+//! strings in single quotes, trailing commas and numbers without a digit before the point, like `.5`.
+
+use std::{error::Error, fmt::Display};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// The members are kept in the order they were written in.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseJsonError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseJsonError {}
+
+struct Parser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: impl Into<String>) -> ParseJsonError {
+        ParseJsonError {
+            line: self.text[..self.offset].matches('\n').count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let character = self.peek()?;
+        self.offset += character.len_utf8();
+        Some(character)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseJsonError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(character) if character == expected => Ok(()),
+            Some(character) => {
+                Err(self.error(format!("Expected '{expected}', found '{character}'")))
+            }
+            None => Err(self.error(format!("Expected '{expected}', found the end"))),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, ParseJsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(quote @ ('"' | '\'')) => {
+                self.next();
+                Ok(JsonValue::String(self.string(quote)?))
+            }
+            Some(character) if matches!(character, '-' | '.') || character.is_ascii_digit() => {
+                self.number()
+            }
+            Some(character) if character.is_alphabetic() => match self.identifier().as_str() {
+                "null" => Ok(JsonValue::Null),
+                "true" => Ok(JsonValue::Bool(true)),
+                "false" => Ok(JsonValue::Bool(false)),
+                other => Err(self.error(format!("Unexpected '{other}'"))),
+            },
+            Some(character) => Err(self.error(format!("Unexpected '{character}'"))),
+            None => Err(self.error("Unexpected end")),
+        }
+    }
+
+    /// Parses values separated by commas until `end`, allowing a trailing comma.
+    fn separated(
+        &mut self,
+        end: char,
+        mut item: impl FnMut(&mut Self) -> Result<(), ParseJsonError>,
+    ) -> Result<(), ParseJsonError> {
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(end) {
+                self.next();
+                return Ok(());
+            }
+            item(self)?;
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(character) if character == end => return Ok(()),
+                _ => return Err(self.error(format!("Expected ',' or '{end}'"))),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, ParseJsonError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.separated(']', |parser| {
+            items.push(parser.value()?);
+            Ok(())
+        })?;
+        Ok(JsonValue::Array(items))
+    }
+
+    fn object(&mut self) -> Result<JsonValue, ParseJsonError> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.separated('}', |parser| {
+            let key = match parser.peek() {
+                Some(quote @ ('"' | '\'')) => {
+                    parser.next();
+                    parser.string(quote)?
+                }
+                _ => parser.identifier(),
+            };
+            if key.is_empty() {
+                return Err(parser.error("Expected a key"));
+            }
+            parser.expect(':')?;
+            members.push((key, parser.value()?));
+            Ok(())
+        })?;
+        Ok(JsonValue::Object(members))
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.offset;
+        while self
+            .peek()
+            .is_some_and(|character| character.is_alphanumeric() || character == '_')
+        {
+            self.next();
+        }
+        self.text[start..self.offset].to_string()
+    }
+
+    /// Parses the rest of a string whose opening quote has been read.
+    fn string(&mut self, quote: char) -> Result<String, ParseJsonError> {
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("Unterminated string")),
+                Some(character) if character == quote => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let mut code = self.hex_escape()?;
+                            // Characters outside the Basic Multilingual Plane are written as a pair of UTF-16 surrogates.
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.offset..].starts_with("\\u")
+                            {
+                                self.next();
+                                self.next();
+                                let low = self.hex_escape()?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                }
+                            }
+                            char::from_u32(code)
+                                .ok_or_else(|| self.error("Invalid unicode escape"))?
+                        }
+                        Some(character) => character,
+                        None => return Err(self.error("Unterminated string")),
+                    };
+                    string.push(escaped);
+                }
+                Some(character) => string.push(character),
+            }
+        }
+    }
+
+    /// Reads the four hex digits after `\u`.
+    fn hex_escape(&mut self) -> Result<u32, ParseJsonError> {
+        let start = self.offset;
+        for _ in 0..4 {
+            self.next();
+        }
+        u32::from_str_radix(&self.text[start..self.offset], 16)
+            .map_err(|_| self.error("Invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<JsonValue, ParseJsonError> {
+        let start = self.offset;
+        while self.peek().is_some_and(|character| {
+            character.is_ascii_digit() || matches!(character, '-' | '+' | '.' | 'e' | 'E')
+        }) {
+            self.next();
+        }
+        let number = &self.text[start..self.offset];
+        number
+            .parse()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error(format!("Invalid number '{number}'")))
+    }
+}
+
+/// Parses a list of values separated by commas, like the contents of an array without the brackets. A single value is a
+/// list of one.
+pub fn parse_values(text: &str) -> Result<Vec<JsonValue>, ParseJsonError> {
+    let mut parser = Parser { text, offset: 0 };
+    let mut values = Vec::new();
+    loop {
+        values.push(parser.value()?);
+        parser.skip_whitespace();
+        match parser.next() {
+            None => return Ok(values),
+            Some(',') => {
+                parser.skip_whitespace();
+                if parser.peek().is_none() {
+                    return Ok(values);
+                }
+            }
+            Some(character) => return Err(parser.error(format!("Unexpected '{character}'"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<JsonValue, ParseJsonError> {
+        parse_values(text).map(|mut values| values.remove(0))
+    }
+
+    #[test]
+    fn surrogate_pairs_make_one_character() {
+        assert_eq!(
+            parse(r#""\ud83d\ude00 \u00e9""#),
+            Ok(JsonValue::String("\u{1f600} \u{e9}".to_string()))
+        );
+        assert!(parse(r#""\ud83d""#).is_err());
+        assert!(parse(r#""\ud83d\u0041""#).is_err());
+    }
+
+    #[test]
+    fn numbers_can_start_with_a_point() {
+        assert_eq!(
+            parse("[.5, -.25, 1e2]"),
+            Ok(JsonValue::Array(vec![
+                JsonValue::Number(0.5),
+                JsonValue::Number(-0.25),
+                JsonValue::Number(100.0),
+            ]))
+        );
+    }
+}
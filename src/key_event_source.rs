@@ -0,0 +1,382 @@
+//! The interface between the platform specific keyboard code and the rest of the program.
+//!
+//! A `KeyEventSource` delivers key presses and releases to its subscribers. Each subscriber gets a `Context` which it can use to
+//! suppress a key press or to send other keystrokes in its place, which is how remapping works. Releases can't be remapped, they
+//! are only delivered so that they can be recorded.
+
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    keyboard::{KeyCode, Keystroke},
+    session::KeyEventKind,
+};
+
+#[derive(Debug)]
+pub struct Context {
+    kind: KeyEventKind,
+    suppress: bool,
+    sent_keystrokes: Vec<Keystroke>,
+    held_keys: BTreeSet<KeyCode>,
+}
+
+impl Context {
+    fn new(kind: KeyEventKind, held_keys: &BTreeSet<KeyCode>) -> Self {
+        Self {
+            kind,
+            suppress: false,
+            sent_keystrokes: Vec::new(),
+            held_keys: held_keys.clone(),
+        }
+    }
+
+    /// Whether the key went down or came up.
+    pub fn kind(&self) -> KeyEventKind {
+        self.kind
+    }
+
+    pub fn suppress(&mut self) {
+        self.suppress = true;
+    }
+
+    /// Sends the keystroke with exactly the modifiers it asks for, whichever ones are actually held.
+    pub fn send_keystroke(&mut self, keystroke: Keystroke) {
+        self.sent_keystrokes.push(keystroke);
+    }
+
+    pub fn is_suppressed(&self) -> bool {
+        self.suppress
+    }
+
+    pub fn sent_keystrokes(&self) -> &[Keystroke] {
+        &self.sent_keystrokes
+    }
+
+    /// The keys which are held down, including the key itself if it was pressed.
+    pub fn held_keys(&self) -> &BTreeSet<KeyCode> {
+        &self.held_keys
+    }
+
+    /// The presses and releases which type the sent keystrokes. Modifiers that a keystroke needs are pressed just for it, and held
+    /// modifiers that it doesn't want are let go of for the moment and pressed again afterwards.
+    pub fn key_events(&self) -> Vec<(KeyCode, KeyEventKind)> {
+        let held_shift_keys: Vec<_> = [KeyCode::LeftShift, KeyCode::RightShift]
+            .into_iter()
+            .filter(|key_code| self.held_keys.contains(key_code))
+            .collect();
+        let alt_gr_held = self.held_keys.contains(&KeyCode::RightAlt);
+        let mut events = Vec::new();
+        for keystroke in &self.sent_keystrokes {
+            let mut before = Vec::new();
+            if keystroke.shift && held_shift_keys.is_empty() {
+                before.push((KeyCode::LeftShift, KeyEventKind::Down));
+            } else if !keystroke.shift {
+                for &key_code in &held_shift_keys {
+                    before.push((key_code, KeyEventKind::Up));
+                }
+            }
+            if keystroke.alt_gr != alt_gr_held {
+                before.push((
+                    KeyCode::RightAlt,
+                    if keystroke.alt_gr {
+                        KeyEventKind::Down
+                    } else {
+                        KeyEventKind::Up
+                    },
+                ));
+            }
+            events.extend_from_slice(&before);
+            events.push((keystroke.key_code, KeyEventKind::Down));
+            events.push((keystroke.key_code, KeyEventKind::Up));
+            // Everything goes back to how it was, in reverse order.
+            events.extend(before.into_iter().rev().map(|(key_code, kind)| {
+                let kind = match kind {
+                    KeyEventKind::Down => KeyEventKind::Up,
+                    KeyEventKind::Up => KeyEventKind::Down,
+                };
+                (key_code, kind)
+            }));
+        }
+        events
+    }
+}
+
+pub type KeyEventCallback = Box<dyn FnMut(&mut Context, KeyCode) + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionId(usize);
+
+#[derive(Clone)]
+struct CallbackEntry {
+    /// Each callback has a lock of its own, so that the list doesn't have to stay locked while it runs.
+    callback: Arc<Mutex<KeyEventCallback>>,
+    id: usize,
+}
+
+#[derive(Default)]
+struct SubscriberList {
+    entries: Vec<CallbackEntry>,
+    next_id: usize,
+}
+
+/// The callbacks subscribed to a source.
+///
+/// This is cheap to clone, and all clones share the same list, so it can be handed to whatever thread actually receives the key presses.
+#[derive(Clone, Default)]
+pub struct Subscribers(Arc<Mutex<SubscriberList>>);
+
+impl Subscribers {
+    pub fn subscribe(&self, callback: KeyEventCallback) -> SubscriptionId {
+        let mut list = self.0.lock().unwrap();
+        let id = list.next_id;
+        list.next_id += 1;
+        list.entries.push(CallbackEntry {
+            callback: Arc::new(Mutex::new(callback)),
+            id,
+        });
+        SubscriptionId(id)
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        let mut list = self.0.lock().unwrap();
+        list.entries.retain(|entry| entry.id != id.0);
+    }
+
+    /// Runs the callbacks for the key event and merges what they asked for. `held_keys` are the keys which are down at the
+    /// time, including this one if it was pressed.
+    ///
+    /// Once a callback suppresses the key press, the remaining callbacks don't get to see it. Callbacks can subscribe and
+    /// unsubscribe while this runs, which takes effect from the next key press.
+    pub fn dispatch(
+        &self,
+        key_code: KeyCode,
+        kind: KeyEventKind,
+        held_keys: &BTreeSet<KeyCode>,
+    ) -> Context {
+        let mut result = Context::new(kind, held_keys);
+        let entries = self.0.lock().unwrap().entries.clone();
+        for entry in entries {
+            let mut context = Context::new(kind, held_keys);
+            (entry.callback.lock().unwrap())(&mut context, key_code);
+            result.sent_keystrokes.append(&mut context.sent_keystrokes);
+            if context.suppress {
+                result.suppress = true;
+                break;
+            }
+        }
+        result
+    }
+
+    /// Checks whether both of these refer to the same list of callbacks.
+    pub fn same_as(&self, other: &Subscribers) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+pub trait KeyEventSource {
+    /// Starts delivering key events to the subscribers.
+    fn start(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Stops delivering key events. The source can be started again later.
+    fn stop(&mut self);
+
+    fn subscribe(&mut self, callback: KeyEventCallback) -> SubscriptionId;
+
+    fn unsubscribe(&mut self, id: SubscriptionId);
+
+    /// Sends a keystroke to the rest of the system, without it being delivered to our own subscribers.
+    fn inject(&mut self, key_code: KeyCode) -> Result<(), Box<dyn Error>>;
+}
+
+/// A source which is fed key presses by hand instead of by the operating system.
+///
+/// Everything which would have reached other applications is recorded in `output`.
+#[derive(Default)]
+pub struct MemoryKeyEventSource {
+    subscribers: Subscribers,
+    running: bool,
+    held_keys: BTreeSet<KeyCode>,
+    output: Vec<Keystroke>,
+}
+
+impl MemoryKeyEventSource {
+    /// Simulates the user pressing the key, and holding it down until `release` is called. Nothing happens if the source isn't
+    /// running.
+    pub fn press(&mut self, key_code: KeyCode) {
+        if !self.running {
+            return;
+        }
+        self.held_keys.insert(key_code);
+        let context = self
+            .subscribers
+            .dispatch(key_code, KeyEventKind::Down, &self.held_keys);
+        self.output.extend_from_slice(context.sent_keystrokes());
+        if !context.is_suppressed() && !key_code.is_modifier() {
+            self.output
+                .push(Keystroke::typed(key_code, &self.held_keys));
+        }
+    }
+
+    pub fn release(&mut self, key_code: KeyCode) {
+        if !self.running {
+            return;
+        }
+        self.held_keys.remove(&key_code);
+        self.subscribers
+            .dispatch(key_code, KeyEventKind::Up, &self.held_keys);
+    }
+
+    /// The keystrokes which were typed, not counting the modifiers on their own.
+    pub fn output(&self) -> &[Keystroke] {
+        &self.output
+    }
+}
+
+impl KeyEventSource for MemoryKeyEventSource {
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        self.running = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.running = false;
+    }
+
+    fn subscribe(&mut self, callback: KeyEventCallback) -> SubscriptionId {
+        self.subscribers.subscribe(callback)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscribers.unsubscribe(id);
+    }
+
+    fn inject(&mut self, key_code: KeyCode) -> Result<(), Box<dyn Error>> {
+        self.output
+            .push(Keystroke::typed(key_code, &self.held_keys));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppressing_stops_later_callbacks() {
+        let subscribers = Subscribers::default();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        for name in ["first", "second"] {
+            let calls = calls.clone();
+            subscribers.subscribe(Box::new(move |context, key_code| {
+                calls.lock().unwrap().push(name);
+                if key_code == KeyCode::A {
+                    context.suppress();
+                    context.send_keystroke(Keystroke::new(KeyCode::B));
+                }
+            }));
+        }
+
+        let context = subscribers.dispatch(
+            KeyCode::A,
+            KeyEventKind::Down,
+            &BTreeSet::from([KeyCode::A]),
+        );
+        assert!(context.is_suppressed());
+        assert_eq!(context.sent_keystrokes(), [Keystroke::new(KeyCode::B)]);
+        assert_eq!(*calls.lock().unwrap(), ["first"]);
+
+        let context = subscribers.dispatch(
+            KeyCode::C,
+            KeyEventKind::Down,
+            &BTreeSet::from([KeyCode::C]),
+        );
+        assert!(!context.is_suppressed());
+        assert_eq!(*calls.lock().unwrap(), ["first", "first", "second"]);
+    }
+
+    #[test]
+    fn callbacks_can_subscribe_and_unsubscribe() {
+        let subscribers = Subscribers::default();
+        let calls = Arc::new(Mutex::new(0));
+        let subscribers_clone = subscribers.clone();
+        let calls_clone = calls.clone();
+        let id = Arc::new(Mutex::new(None));
+        let id_clone = id.clone();
+        let own_id = subscribers.subscribe(Box::new(move |_, _| {
+            let calls = calls_clone.clone();
+            let new_id = subscribers_clone.subscribe(Box::new(move |_, _| {
+                *calls.lock().unwrap() += 1;
+            }));
+            if let Some(old_id) = id_clone.lock().unwrap().replace(new_id) {
+                subscribers_clone.unsubscribe(old_id);
+            }
+        }));
+
+        subscribers.dispatch(KeyCode::A, KeyEventKind::Down, &BTreeSet::new());
+        assert_eq!(*calls.lock().unwrap(), 0);
+        subscribers.dispatch(KeyCode::A, KeyEventKind::Down, &BTreeSet::new());
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        subscribers.unsubscribe(own_id);
+        subscribers.dispatch(KeyCode::A, KeyEventKind::Down, &BTreeSet::new());
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn releases_are_delivered_too() {
+        let mut source = MemoryKeyEventSource::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_clone = events.clone();
+        source.subscribe(Box::new(move |context, key_code| {
+            events_clone.lock().unwrap().push((
+                key_code,
+                context.kind(),
+                context.held_keys().clone(),
+            ));
+        }));
+        source.start().unwrap();
+        source.press(KeyCode::LeftShift);
+        source.press(KeyCode::A);
+        source.release(KeyCode::A);
+        source.release(KeyCode::LeftShift);
+
+        let shift = BTreeSet::from([KeyCode::LeftShift]);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                (KeyCode::LeftShift, KeyEventKind::Down, shift.clone()),
+                (
+                    KeyCode::A,
+                    KeyEventKind::Down,
+                    BTreeSet::from([KeyCode::LeftShift, KeyCode::A])
+                ),
+                (KeyCode::A, KeyEventKind::Up, shift),
+                (KeyCode::LeftShift, KeyEventKind::Up, BTreeSet::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_source_only_delivers_while_running() {
+        let mut source = MemoryKeyEventSource::default();
+        source.press(KeyCode::A);
+        assert!(source.output().is_empty());
+
+        source.start().unwrap();
+        source.press(KeyCode::LeftShift);
+        source.press(KeyCode::A);
+        source.release(KeyCode::A);
+        source.release(KeyCode::LeftShift);
+        source.inject(KeyCode::B).unwrap();
+        source.stop();
+        source.press(KeyCode::C);
+
+        assert_eq!(
+            source.output(),
+            [Keystroke::shifted(KeyCode::A), Keystroke::new(KeyCode::B)]
+        );
+    }
+}
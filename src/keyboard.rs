@@ -1,5 +1,12 @@
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+};
 
+/// A physical key. The keys are named after what they type on US QWERTY, but what they type depends on the keyboard layout that
+/// the system uses (see `CharacterMap`), and on what a layout puts at their position.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum KeyCode {
     Q,
@@ -32,6 +39,203 @@ pub enum KeyCode {
     Comma,
     Dot,
     Slash,
+    // The rest of the main block. These come after the letter keys so that the numbers of the letter keys stay the same.
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Minus,
+    Equal,
+    Backspace,
+    Tab,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    CapsLock,
+    Apostrophe,
+    Enter,
+    LeftShift,
+    RightShift,
+    Grave,
+    LeftControl,
+    LeftMeta,
+    LeftAlt,
+    Space,
+    RightAlt,
+    RightMeta,
+    RightControl,
+}
+
+impl KeyCode {
+    pub const ALL: [KeyCode; 60] = [
+        KeyCode::Q,
+        KeyCode::W,
+        KeyCode::E,
+        KeyCode::R,
+        KeyCode::T,
+        KeyCode::Y,
+        KeyCode::U,
+        KeyCode::I,
+        KeyCode::O,
+        KeyCode::P,
+        KeyCode::A,
+        KeyCode::S,
+        KeyCode::D,
+        KeyCode::F,
+        KeyCode::G,
+        KeyCode::H,
+        KeyCode::J,
+        KeyCode::K,
+        KeyCode::L,
+        KeyCode::Semicolon,
+        KeyCode::Z,
+        KeyCode::X,
+        KeyCode::C,
+        KeyCode::V,
+        KeyCode::B,
+        KeyCode::N,
+        KeyCode::M,
+        KeyCode::Comma,
+        KeyCode::Dot,
+        KeyCode::Slash,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+        KeyCode::Digit0,
+        KeyCode::Minus,
+        KeyCode::Equal,
+        KeyCode::Backspace,
+        KeyCode::Tab,
+        KeyCode::LeftBracket,
+        KeyCode::RightBracket,
+        KeyCode::Backslash,
+        KeyCode::CapsLock,
+        KeyCode::Apostrophe,
+        KeyCode::Enter,
+        KeyCode::LeftShift,
+        KeyCode::RightShift,
+        KeyCode::Grave,
+        KeyCode::LeftControl,
+        KeyCode::LeftMeta,
+        KeyCode::LeftAlt,
+        KeyCode::Space,
+        KeyCode::RightAlt,
+        KeyCode::RightMeta,
+        KeyCode::RightControl,
+    ];
+
+    /// A number which identifies this key code, for storing it in files.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<KeyCode> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// Where the key physically is. Rows 0 to 2 are the letter rows, with the keys to the right of the letters from column 10 on.
+    /// Row 3 is the number row, row 4 is the bottom row with the space bar, and row 5 has the keys to the left of the letters.
+    pub fn position(self) -> (usize, usize) {
+        match self {
+            KeyCode::LeftBracket => (0, 10),
+            KeyCode::RightBracket => (0, 11),
+            KeyCode::Backslash => (0, 12),
+            KeyCode::Apostrophe => (1, 10),
+            KeyCode::Enter => (1, 11),
+            KeyCode::RightShift => (2, 10),
+            KeyCode::LeftControl => (4, 0),
+            KeyCode::LeftMeta => (4, 1),
+            KeyCode::LeftAlt => (4, 2),
+            KeyCode::Space => (4, 3),
+            KeyCode::RightAlt => (4, 4),
+            KeyCode::RightMeta => (4, 5),
+            KeyCode::RightControl => (4, 6),
+            KeyCode::Grave => (5, 0),
+            KeyCode::Tab => (5, 1),
+            KeyCode::CapsLock => (5, 2),
+            KeyCode::LeftShift => (5, 3),
+            // The letter keys and the number row are in order.
+            _ if self.index() < 30 => (self.index() / 10, self.index() % 10),
+            _ => (3, self.index() - 30),
+        }
+    }
+
+    /// Which physical row the position is on, counting down from the number row, so the letter rows are 1 to 3 and the space
+    /// bar's row is 4. The keys left of the letters are on the row next to them. Rows past these count as themselves.
+    pub fn physical_row(position: (usize, usize)) -> usize {
+        match position {
+            (row, _) if row < 3 => row + 1,
+            (3, _) => 0,
+            (5, column) => column.min(3),
+            (row, _) => row,
+        }
+    }
+
+    /// The key which is physically at the position, if any.
+    pub fn at_position(position: (usize, usize)) -> Option<KeyCode> {
+        Self::ALL
+            .into_iter()
+            .find(|key_code| key_code.position() == position)
+    }
+
+    /// Finds the key which types the character on a US QWERTY keyboard, with or without shift.
+    pub fn from_character(character: char) -> Option<KeyCode> {
+        match character.to_ascii_lowercase() {
+            ';' | ':' => Some(KeyCode::Semicolon),
+            ',' | '<' => Some(KeyCode::Comma),
+            '.' | '>' => Some(KeyCode::Dot),
+            '/' | '?' => Some(KeyCode::Slash),
+            '1' | '!' => Some(KeyCode::Digit1),
+            '2' | '@' => Some(KeyCode::Digit2),
+            '3' | '#' => Some(KeyCode::Digit3),
+            '4' | '$' => Some(KeyCode::Digit4),
+            '5' | '%' => Some(KeyCode::Digit5),
+            '6' | '^' => Some(KeyCode::Digit6),
+            '7' | '&' => Some(KeyCode::Digit7),
+            '8' | '*' => Some(KeyCode::Digit8),
+            '9' | '(' => Some(KeyCode::Digit9),
+            '0' | ')' => Some(KeyCode::Digit0),
+            '-' | '_' => Some(KeyCode::Minus),
+            '=' | '+' => Some(KeyCode::Equal),
+            '[' | '{' => Some(KeyCode::LeftBracket),
+            ']' | '}' => Some(KeyCode::RightBracket),
+            '\\' | '|' => Some(KeyCode::Backslash),
+            '\'' | '"' => Some(KeyCode::Apostrophe),
+            '`' | '~' => Some(KeyCode::Grave),
+            ' ' => Some(KeyCode::Space),
+            '\n' => Some(KeyCode::Enter),
+            '\t' => Some(KeyCode::Tab),
+            letter @ 'a'..='z' => letter.to_string().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether the key is Shift, Control, Alt or Meta, which change what other keys do instead of typing anything.
+    pub fn is_modifier(self) -> bool {
+        matches!(
+            self,
+            KeyCode::LeftShift
+                | KeyCode::RightShift
+                | KeyCode::LeftControl
+                | KeyCode::RightControl
+                | KeyCode::LeftAlt
+                | KeyCode::RightAlt
+                | KeyCode::LeftMeta
+                | KeyCode::RightMeta
+        )
+    }
 }
 
 impl Display for KeyCode {
@@ -67,101 +271,261 @@ impl Display for KeyCode {
             KeyCode::Comma => write!(f, ","),
             KeyCode::Dot => write!(f, "."),
             KeyCode::Slash => write!(f, "/"),
+            KeyCode::Digit1 => write!(f, "1"),
+            KeyCode::Digit2 => write!(f, "2"),
+            KeyCode::Digit3 => write!(f, "3"),
+            KeyCode::Digit4 => write!(f, "4"),
+            KeyCode::Digit5 => write!(f, "5"),
+            KeyCode::Digit6 => write!(f, "6"),
+            KeyCode::Digit7 => write!(f, "7"),
+            KeyCode::Digit8 => write!(f, "8"),
+            KeyCode::Digit9 => write!(f, "9"),
+            KeyCode::Digit0 => write!(f, "0"),
+            KeyCode::Minus => write!(f, "-"),
+            KeyCode::Equal => write!(f, "="),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::LeftBracket => write!(f, "["),
+            KeyCode::RightBracket => write!(f, "]"),
+            KeyCode::Backslash => write!(f, "\\"),
+            KeyCode::CapsLock => write!(f, "CapsLock"),
+            KeyCode::Apostrophe => write!(f, "'"),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::LeftShift => write!(f, "LeftShift"),
+            KeyCode::RightShift => write!(f, "RightShift"),
+            KeyCode::Grave => write!(f, "`"),
+            KeyCode::LeftControl => write!(f, "LeftControl"),
+            KeyCode::LeftMeta => write!(f, "LeftMeta"),
+            KeyCode::LeftAlt => write!(f, "LeftAlt"),
+            KeyCode::Space => write!(f, "Space"),
+            KeyCode::RightAlt => write!(f, "RightAlt"),
+            KeyCode::RightMeta => write!(f, "RightMeta"),
+            KeyCode::RightControl => write!(f, "RightControl"),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKeyCodeError(String);
+
+impl Display for ParseKeyCodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown key '{}'", self.0)
+    }
+}
+
+impl Error for ParseKeyCodeError {}
+
+impl FromStr for KeyCode {
+    type Err = ParseKeyCodeError;
+
+    /// Parses the name that `Display` gives the key code, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeyCode::ALL
+            .into_iter()
+            .find(|key_code| key_code.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseKeyCodeError(s.to_string()))
+    }
+}
+
+/// The characters that the keys type with Shift on US QWERTY, apart from the capital letters.
+const SHIFTED_CHARACTERS: &str = "~!@#$%^&*()_+{}|:\"<>?";
+
+/// A key along with whether Shift and AltGr (the right Alt key) are held while it is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Keystroke {
+    pub key_code: KeyCode,
+    pub shift: bool,
+    pub alt_gr: bool,
+}
+
+impl Keystroke {
+    /// The key on its own, without any modifiers.
+    pub fn new(key_code: KeyCode) -> Self {
+        Self {
+            key_code,
+            shift: false,
+            alt_gr: false,
+        }
+    }
+
+    pub fn shifted(key_code: KeyCode) -> Self {
+        Self {
+            shift: true,
+            ..Self::new(key_code)
+        }
+    }
+
+    /// What pressing the key does while `held_keys` are held down.
+    pub fn typed(key_code: KeyCode, held_keys: &BTreeSet<KeyCode>) -> Self {
+        Self {
+            key_code,
+            shift: held_keys.contains(&KeyCode::LeftShift)
+                || held_keys.contains(&KeyCode::RightShift),
+            alt_gr: held_keys.contains(&KeyCode::RightAlt),
+        }
+    }
+
+    /// The keystroke which types the character on US QWERTY.
+    pub fn from_character(character: char) -> Option<Self> {
+        let key_code = KeyCode::from_character(character)?;
+        if character.is_ascii_uppercase() || SHIFTED_CHARACTERS.contains(character) {
+            Some(Self::shifted(key_code))
+        } else {
+            Some(Self::new(key_code))
+        }
+    }
+}
+
+impl Display for Keystroke {
+    /// Shifted symbols are shown as the symbol, like `(` for Shift+9, and everything else as `Shift+` and `AltGr+` in front of
+    /// the key.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.alt_gr {
+            write!(f, "AltGr+")?;
+        }
+        if self.shift {
+            match SHIFTED_CHARACTERS
+                .chars()
+                .find(|&character| KeyCode::from_character(character) == Some(self.key_code))
+            {
+                Some(character) => return write!(f, "{character}"),
+                None => write!(f, "Shift+")?,
+            }
+        }
+        write!(f, "{}", self.key_code)
+    }
+}
+
+fn strip_prefix_ignoring_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    s.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[prefix.len()..])
+}
+
+impl FromStr for Keystroke {
+    type Err = ParseKeyCodeError;
+
+    /// Parses what `Display` shows, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = strip_prefix_ignoring_case(s, "AltGr+") {
+            return Ok(Self {
+                alt_gr: true,
+                ..rest.parse()?
+            });
+        }
+        if let Some(rest) = strip_prefix_ignoring_case(s, "Shift+") {
+            return Ok(Self::shifted(rest.parse()?));
+        }
+        let mut characters = s.chars();
+        match (characters.next(), characters.next()) {
+            (Some(character), None) if SHIFTED_CHARACTERS.contains(character) => {
+                Ok(Self::from_character(character).unwrap())
+            }
+            _ => Ok(Self::new(s.parse()?)),
+        }
+    }
+}
+
+/// Which key is at each position. Positions are (row, column) pairs, where rows 0 to 2 and columns 0 to 9 are the usual letter
+/// keys, but a layout can use any set of positions, like extra pinky columns or thumb keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KeyboardLayout {
-    pub top_row: [KeyCode; 10],
-    pub middle_row: [KeyCode; 10],
-    pub bottom_row: [KeyCode; 10],
+    keys: BTreeMap<(usize, usize), KeyCode>,
 }
 
 impl Default for KeyboardLayout {
     fn default() -> Self {
-        Self::QWERTY
+        Self::qwerty()
     }
 }
 
 impl KeyboardLayout {
-    pub const QWERTY: KeyboardLayout = KeyboardLayout {
-        top_row: [
-            KeyCode::Q,
-            KeyCode::W,
-            KeyCode::E,
-            KeyCode::R,
-            KeyCode::T,
-            KeyCode::Y,
-            KeyCode::U,
-            KeyCode::I,
-            KeyCode::O,
-            KeyCode::P,
-        ],
-        middle_row: [
-            KeyCode::A,
-            KeyCode::S,
-            KeyCode::D,
-            KeyCode::F,
-            KeyCode::G,
-            KeyCode::H,
-            KeyCode::J,
-            KeyCode::K,
-            KeyCode::L,
-            KeyCode::Semicolon,
-        ],
-        bottom_row: [
-            KeyCode::Z,
-            KeyCode::X,
-            KeyCode::C,
-            KeyCode::V,
-            KeyCode::B,
-            KeyCode::N,
-            KeyCode::M,
-            KeyCode::Comma,
-            KeyCode::Dot,
-            KeyCode::Slash,
-        ],
-    };
+    pub fn new(keys: BTreeMap<(usize, usize), KeyCode>) -> Self {
+        Self { keys }
+    }
 
-    pub fn position_of(&self, key_code: KeyCode) -> Option<(usize, usize)> {
-        for (row_index, row) in [self.top_row, self.middle_row, self.bottom_row]
-            .iter()
-            .enumerate()
-        {
-            for (column_index, &code) in row.iter().enumerate() {
-                if code == key_code {
-                    return Some((row_index, column_index));
-                }
+    /// Makes a layout from rows of keys, each starting at column 0.
+    pub fn from_rows(rows: &[&[KeyCode]]) -> Self {
+        let mut layout = Self::new(BTreeMap::new());
+        for (row_index, row) in rows.iter().enumerate() {
+            for (column_index, &key_code) in row.iter().enumerate() {
+                layout.set_key_at(row_index, column_index, key_code);
             }
         }
-        None
+        layout
     }
 
-    pub fn key_at(&self, position: (usize, usize)) -> KeyCode {
-        let (row_index, column_index) = position;
-        match row_index {
-            0 => self.top_row[column_index],
-            1 => self.middle_row[column_index],
-            2 => self.bottom_row[column_index],
-            _ => panic!("Invalid row index"),
-        }
+    pub fn qwerty() -> Self {
+        Self::from_rows(&[
+            &KeyCode::ALL[0..10],
+            &KeyCode::ALL[10..20],
+            &KeyCode::ALL[20..30],
+        ])
+    }
+
+    /// Every key of the main block, each at its physical position (see `KeyCode::position`). The letter keys have the same
+    /// positions as in `qwerty`.
+    pub fn qwerty_main_block() -> Self {
+        Self::new(
+            KeyCode::ALL
+                .into_iter()
+                .map(|key_code| (key_code.position(), key_code))
+                .collect(),
+        )
     }
 
+    pub fn position_of(&self, key_code: KeyCode) -> Option<(usize, usize)> {
+        self.keys
+            .iter()
+            .find(|(_, &code)| code == key_code)
+            .map(|(&position, _)| position)
+    }
+
+    /// The key at the position, or `None` if the position isn't part of the layout.
+    pub fn key_at(&self, position: (usize, usize)) -> Option<KeyCode> {
+        self.keys.get(&position).copied()
+    }
+
+    /// Puts a key at a position, adding the position to the layout if it wasn't part of it yet.
     pub fn set_key_at(&mut self, row_index: usize, column_index: usize, key_code: KeyCode) {
-        match row_index {
-            0 => self.top_row[column_index] = key_code,
-            1 => self.middle_row[column_index] = key_code,
-            2 => self.bottom_row[column_index] = key_code,
-            _ => panic!("Invalid row index"),
+        self.keys.insert((row_index, column_index), key_code);
+    }
+
+    /// Swaps the keys at the two positions. If only one of them has a key, the key moves to the other one.
+    pub fn swap(&mut self, first: (usize, usize), second: (usize, usize)) {
+        let first_key = self.keys.remove(&first);
+        let second_key = self.keys.remove(&second);
+        if let Some(key_code) = second_key {
+            self.keys.insert(first, key_code);
+        }
+        if let Some(key_code) = first_key {
+            self.keys.insert(second, key_code);
         }
     }
 
+    /// The positions of the layout, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.keys.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The keys of the layout, row by row.
     pub fn iter(&self) -> impl Iterator<Item = KeyCode> + '_ {
-        self.top_row
+        self.keys.values().copied()
+    }
+
+    /// Each position along with its key, row by row.
+    pub fn keys(&self) -> impl Iterator<Item = ((usize, usize), KeyCode)> + '_ {
+        self.keys
             .iter()
-            .copied()
-            .chain(self.middle_row.iter().copied())
-            .chain(self.bottom_row.iter().copied())
+            .map(|(&position, &key_code)| (position, key_code))
     }
 }
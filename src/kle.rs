@@ -0,0 +1,360 @@
+//! Reads the physical layout of a keyboard from keyboard-layout-editor.com (KLE) JSON.
+//!
+//! Both the "Raw data" text from the editor and downloaded JSON files work. Each row of the KLE data becomes a row of
+//! positions, with the keys numbered from the left. Properties like `x`, `y`, `w` and `h` move and resize the key after them,
+//! the same way KLE does. Rotated keys (`r`, `rx` and `ry`) have their center rotated, but stay upright themselves, since
+//! `KeyGeometry` has no rotation. Colours, fonts and the other looks-only properties are ignored.
+
+use std::{collections::BTreeSet, error::Error, fmt::Display, fs, io, path::Path, str::FromStr};
+
+use crate::{
+    geometry::{KeyGeometry, KeyboardGeometry},
+    json::{parse_values, JsonValue, ParseJsonError},
+    keyboard::{KeyCode, KeyboardLayout},
+};
+
+/// The row that `KleKeyboard::geometry_for` puts the keys it can't match up on. It's the first one past the rows that
+/// `KeyCode::position` uses.
+pub const EXTRA_ROW: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseKleError {
+    Json(ParseJsonError),
+    /// A row (counting from 1) has something in it which is neither a key legend nor properties.
+    InvalidRow(usize),
+}
+
+impl Display for ParseKleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseKleError::Json(error) => error.fmt(f),
+            ParseKleError::InvalidRow(row) => {
+                write!(
+                    f,
+                    "Row {row} has something which is neither a key nor properties"
+                )
+            }
+        }
+    }
+}
+
+impl Error for ParseKleError {}
+
+impl From<ParseJsonError> for ParseKleError {
+    fn from(error: ParseJsonError) -> Self {
+        ParseKleError::Json(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KleKey {
+    /// The row and column of the key in the KLE data.
+    pub position: (usize, usize),
+    /// The key's legends, one per line, as written in KLE.
+    pub legend: String,
+    pub geometry: KeyGeometry,
+}
+
+impl KleKey {
+    /// The key that the legend names, if any. A line of the legend has to be just that character or the key's name, so "<\n,"
+    /// is the comma key and "Tab" is the tab key, but "Shift" isn't the S key.
+    pub fn key_code(&self) -> Option<KeyCode> {
+        self.legend.lines().find_map(|line| {
+            let mut characters = line.chars();
+            match (characters.next(), characters.next()) {
+                (Some(character), None) => KeyCode::from_character(character),
+                _ => line.parse().ok(),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KleKeyboard {
+    keys: Vec<KleKey>,
+}
+
+/// The properties that apply to the next key, and the ones which carry on to the keys after it.
+struct KeyState {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    rotation: f64,
+    rotation_x: f64,
+    rotation_y: f64,
+}
+
+impl KeyState {
+    fn apply(&mut self, properties: &JsonValue) {
+        let property = |name| properties.get(name).and_then(JsonValue::as_f64);
+        if let Some(rotation) = property("r") {
+            self.rotation = rotation;
+        }
+        // A new rotation origin starts a new cluster of keys there.
+        if let Some(rotation_x) = property("rx") {
+            self.rotation_x = rotation_x;
+            self.x = rotation_x;
+            self.y = self.rotation_y;
+        }
+        if let Some(rotation_y) = property("ry") {
+            self.rotation_y = rotation_y;
+            self.x = self.rotation_x;
+            self.y = rotation_y;
+        }
+        self.x += property("x").unwrap_or(0.0);
+        self.y += property("y").unwrap_or(0.0);
+        if let Some(width) = property("w") {
+            self.width = width;
+        }
+        if let Some(height) = property("h") {
+            self.height = height;
+        }
+    }
+
+    fn key_geometry(&self) -> KeyGeometry {
+        let (center_x, center_y) = (self.x + self.width / 2.0, self.y + self.height / 2.0);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (offset_x, offset_y) = (center_x - self.rotation_x, center_y - self.rotation_y);
+        let center_x = self.rotation_x + offset_x * cos - offset_y * sin;
+        let center_y = self.rotation_y + offset_x * sin + offset_y * cos;
+        KeyGeometry {
+            x: center_x - self.width / 2.0,
+            y: center_y - self.height / 2.0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl KleKeyboard {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn keys(&self) -> &[KleKey] {
+        &self.keys
+    }
+
+    /// The geometry of the keys in KLE's rows and columns.
+    pub fn geometry(&self) -> KeyboardGeometry {
+        KeyboardGeometry::new(
+            self.keys
+                .iter()
+                .map(|key| (key.position, key.geometry))
+                .collect(),
+        )
+    }
+
+    /// The geometry of the keys, at the positions the keys have in `layout`. This is what lets the evaluator and the GUI work with
+    /// the board. Keys without a legend we know (and second keys with the same legend) go on the extra rows, see `extra_keys`.
+    pub fn geometry_for(&self, layout: &KeyboardLayout) -> KeyboardGeometry {
+        KeyboardGeometry::new(
+            self.placed_keys(layout)
+                .into_iter()
+                .map(|(position, key)| (position, key.geometry))
+                .collect(),
+        )
+    }
+
+    /// The keys which aren't in `layout`, like thumb keys or the second space bar of a split keyboard, with the positions that
+    /// `geometry_for` gives them. They are numbered in KLE's order along `EXTRA_ROW`, so that a template can put keys there.
+    pub fn extra_keys(&self, layout: &KeyboardLayout) -> Vec<((usize, usize), &KleKey)> {
+        self.placed_keys(layout)
+            .into_iter()
+            .filter(|&((row, _), _)| row == EXTRA_ROW)
+            .collect()
+    }
+
+    fn placed_keys(&self, layout: &KeyboardLayout) -> Vec<((usize, usize), &KleKey)> {
+        let mut taken = BTreeSet::new();
+        let mut extra_keys = 0;
+        let mut placed = Vec::new();
+        for key in &self.keys {
+            let position = match key
+                .key_code()
+                .and_then(|key_code| layout.position_of(key_code))
+            {
+                Some(position) if taken.insert(position) => position,
+                _ => {
+                    extra_keys += 1;
+                    (EXTRA_ROW, extra_keys - 1)
+                }
+            };
+            placed.push((position, key));
+        }
+        placed
+    }
+}
+
+impl FromStr for KleKeyboard {
+    type Err = ParseKleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = parse_values(s)?;
+        // Downloaded files wrap the rows in an array, while the raw data is just the rows separated by commas.
+        let rows = match values.as_slice() {
+            [JsonValue::Array(items)]
+                if items
+                    .iter()
+                    .all(|item| matches!(item, JsonValue::Array(_) | JsonValue::Object(_))) =>
+            {
+                items.clone()
+            }
+            _ => values,
+        };
+        let mut state = KeyState {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            rotation: 0.0,
+            rotation_x: 0.0,
+            rotation_y: 0.0,
+        };
+        let mut keys = Vec::new();
+        let mut row_index = 0;
+        for row in &rows {
+            // An object on its own (usually first) holds the keyboard's name and other details.
+            let JsonValue::Array(items) = row else {
+                continue;
+            };
+            let mut column_index = 0;
+            for item in items {
+                match item {
+                    JsonValue::String(legend) => {
+                        keys.push(KleKey {
+                            position: (row_index, column_index),
+                            legend: legend.clone(),
+                            geometry: state.key_geometry(),
+                        });
+                        column_index += 1;
+                        state.x += state.width;
+                        state.width = 1.0;
+                        state.height = 1.0;
+                    }
+                    JsonValue::Object(_) => state.apply(item),
+                    _ => return Err(ParseKleError::InvalidRow(row_index + 1)),
+                }
+            }
+            row_index += 1;
+            state.y += 1.0;
+            state.x = state.rotation_x;
+        }
+        // KLE puts the top left corner anywhere it likes, but the geometry should start at 0, 0.
+        let min_x = keys
+            .iter()
+            .map(|key| key.geometry.x)
+            .fold(f64::INFINITY, f64::min);
+        let min_y = keys
+            .iter()
+            .map(|key| key.geometry.y)
+            .fold(f64::INFINITY, f64::min);
+        for key in &mut keys {
+            key.geometry.x -= min_x;
+            key.geometry.y -= min_y;
+        }
+        Ok(Self { keys })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry_of<'a>(keyboard: &'a KleKeyboard, legend: &str) -> &'a KeyGeometry {
+        &keyboard
+            .keys()
+            .iter()
+            .find(|key| key.legend == legend)
+            .unwrap()
+            .geometry
+    }
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-9 && (actual.1 - expected.1).abs() < 1e-9,
+            "{actual:?} isn't {expected:?}"
+        );
+    }
+
+    #[test]
+    fn keys_move_along_their_rows() {
+        let keyboard: KleKeyboard = r#"["Q","W",{"x":0.5,"w":2},"E","R"],["A"]"#.parse().unwrap();
+        let positions: Vec<_> = keyboard.keys().iter().map(|key| key.position).collect();
+        assert_eq!(positions, [(0, 0), (0, 1), (0, 2), (0, 3), (1, 0)]);
+        assert_eq!(*geometry_of(&keyboard, "W"), KeyGeometry::new(1.0, 0.0));
+        // The width only applies to the key after it, but the gap moves every key after it along.
+        let e = geometry_of(&keyboard, "E");
+        assert_eq!((e.x, e.width), (2.5, 2.0));
+        assert_eq!(*geometry_of(&keyboard, "R"), KeyGeometry::new(4.5, 0.0));
+        assert_eq!(*geometry_of(&keyboard, "A"), KeyGeometry::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn keyboards_start_at_the_top_left() {
+        let keyboard: KleKeyboard =
+            r#"[{"name":"Offset"},[{"x":2,"y":3},"A","B"]]"#.parse().unwrap();
+        assert_eq!(*geometry_of(&keyboard, "A"), KeyGeometry::new(0.0, 0.0));
+        assert_eq!(*geometry_of(&keyboard, "B"), KeyGeometry::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn rotated_keys_have_their_centers_rotated() {
+        let keyboard: KleKeyboard = r#"["B"],[{"r":90,"rx":1,"ry":1},"A"]"#.parse().unwrap();
+        // A's center starts half a key right and down of the origin (1, 1), so a quarter turn puts it half a key left and down.
+        let a = geometry_of(&keyboard, "A");
+        assert_close((a.x, a.y), (0.0, 1.0));
+        assert_eq!(*geometry_of(&keyboard, "B"), KeyGeometry::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn any_line_of_a_legend_can_name_the_key() {
+        let keyboard: KleKeyboard = r#"["<\n,","!\n1","Tab","Shift",""]"#.parse().unwrap();
+        let key_codes: Vec<_> = keyboard.keys().iter().map(KleKey::key_code).collect();
+        assert_eq!(
+            key_codes,
+            [
+                Some(KeyCode::Comma),
+                Some(KeyCode::Digit1),
+                Some(KeyCode::Tab),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_data_is_an_error() {
+        assert!(matches!(
+            r#"["A","#.parse::<KleKeyboard>(),
+            Err(ParseKleError::Json(_))
+        ));
+        assert_eq!(
+            r#"["A"],["B",1]"#.parse::<KleKeyboard>(),
+            Err(ParseKleError::InvalidRow(2))
+        );
+    }
+
+    #[test]
+    fn keys_that_cant_be_matched_go_on_the_extra_row() {
+        let keyboard: KleKeyboard = r#"["Q","Fn",{"w":2},"Q"]"#.parse().unwrap();
+        let qwerty = KeyboardLayout::qwerty_main_block();
+        let geometry = keyboard.geometry_for(&qwerty);
+        let q = qwerty.position_of(KeyCode::Q).unwrap();
+        assert_eq!(geometry.key(q), Some(&KeyGeometry::new(0.0, 0.0)));
+        assert_eq!(
+            geometry.key((EXTRA_ROW, 0)),
+            Some(&KeyGeometry::new(1.0, 0.0))
+        );
+        assert_eq!(geometry.key((EXTRA_ROW, 1)).map(|key| key.width), Some(2.0));
+        let extra_keys: Vec<_> = keyboard
+            .extra_keys(&qwerty)
+            .into_iter()
+            .map(|(position, key)| (position, key.legend.as_str()))
+            .collect();
+        assert_eq!(extra_keys, [((EXTRA_ROW, 0), "Fn"), ((EXTRA_ROW, 1), "Q")]);
+    }
+}
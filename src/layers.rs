@@ -0,0 +1,163 @@
+//! Layouts with more than one layer, so that shifted characters and symbols can be placed as well as the base keys.
+//!
+//! The base layer is an ordinary `KeyboardLayout`. Every other layer is active while one of its activation keys is held, and
+//! says what keystroke (a key, with or without Shift and AltGr) each of its positions types. Positions that a layer leaves
+//! empty fall through to the layers under it, and in the end to the base layer, which is typed with whatever modifiers are
+//! held. When more than one layer is active, the one added last wins. A layer without any activation keys is always active,
+//! which lets the base layer have keystrokes with modifiers in it.
+//!
+//! Activation keys which are modifiers (like Shift for the Shift layer) still reach the system, so that shortcuts keep working.
+//! Any other activation key, like Caps Lock for a symbol layer, does nothing but switch layers.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::keyboard::{KeyCode, KeyboardLayout, Keystroke};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    name: String,
+    activation_keys: Vec<KeyCode>,
+    keys: BTreeMap<(usize, usize), Keystroke>,
+}
+
+impl Layer {
+    /// An empty layer, which is active while any of `activation_keys` is held, or all the time if there are none.
+    pub fn new(name: impl Into<String>, activation_keys: Vec<KeyCode>) -> Self {
+        Self {
+            name: name.into(),
+            activation_keys,
+            keys: BTreeMap::new(),
+        }
+    }
+
+    pub fn shift() -> Self {
+        Self::new("Shift", vec![KeyCode::LeftShift, KeyCode::RightShift])
+    }
+
+    pub fn alt_gr() -> Self {
+        Self::new("AltGr", vec![KeyCode::RightAlt])
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn activation_keys(&self) -> &[KeyCode] {
+        &self.activation_keys
+    }
+
+    pub fn is_active(&self, held_keys: &BTreeSet<KeyCode>) -> bool {
+        self.activation_keys.is_empty()
+            || self
+                .activation_keys
+                .iter()
+                .any(|key_code| held_keys.contains(key_code))
+    }
+
+    /// The keystroke at the position, or `None` if the layer leaves it to the layers under it.
+    pub fn keystroke_at(&self, position: (usize, usize)) -> Option<Keystroke> {
+        self.keys.get(&position).copied()
+    }
+
+    pub fn set_keystroke_at(&mut self, position: (usize, usize), keystroke: Keystroke) {
+        self.keys.insert(position, keystroke);
+    }
+
+    /// Makes the position fall through to the layers under it again.
+    pub fn clear_keystroke_at(&mut self, position: (usize, usize)) {
+        self.keys.remove(&position);
+    }
+
+    /// Each position that the layer has a keystroke for, row by row.
+    pub fn keystrokes(&self) -> impl Iterator<Item = ((usize, usize), Keystroke)> + '_ {
+        self.keys
+            .iter()
+            .map(|(&position, &keystroke)| (position, keystroke))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayeredLayout {
+    base: KeyboardLayout,
+    layers: Vec<Layer>,
+}
+
+impl From<KeyboardLayout> for LayeredLayout {
+    fn from(base: KeyboardLayout) -> Self {
+        Self::new(base)
+    }
+}
+
+impl LayeredLayout {
+    /// A layout with `base` as its base layer and empty Shift and AltGr layers, so that it types the same as `base` does.
+    pub fn new(base: KeyboardLayout) -> Self {
+        Self {
+            base,
+            layers: vec![Layer::shift(), Layer::alt_gr()],
+        }
+    }
+
+    /// A layout with exactly these layers, from the bottom up.
+    pub fn with_layers(base: KeyboardLayout, layers: Vec<Layer>) -> Self {
+        Self { base, layers }
+    }
+
+    pub fn base(&self) -> &KeyboardLayout {
+        &self.base
+    }
+
+    pub fn base_mut(&mut self) -> &mut KeyboardLayout {
+        &mut self.base
+    }
+
+    /// The layers on top of the base layer, from the bottom up.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    /// Puts the layer on top of the others, replacing any layer with the same name.
+    pub fn add_layer(&mut self, layer: Layer) {
+        self.layers.retain(|other| other.name != layer.name);
+        self.layers.push(layer);
+    }
+
+    pub fn remove_layer(&mut self, name: &str) -> Option<Layer> {
+        let index = self.layers.iter().position(|layer| layer.name == name)?;
+        Some(self.layers.remove(index))
+    }
+
+    /// Whether the key switches to a layer instead of typing anything.
+    pub fn is_layer_key(&self, key_code: KeyCode) -> bool {
+        !key_code.is_modifier()
+            && self
+                .layers
+                .iter()
+                .any(|layer| layer.activation_keys.contains(&key_code))
+    }
+
+    /// What the key at the position types while `held_keys` are held, or `None` if the position isn't part of the layout.
+    pub fn keystroke_at(
+        &self,
+        position: (usize, usize),
+        held_keys: &BTreeSet<KeyCode>,
+    ) -> Option<Keystroke> {
+        self.layers
+            .iter()
+            .rev()
+            .filter(|layer| layer.is_active(held_keys))
+            .find_map(|layer| layer.keystroke_at(position))
+            .or_else(|| {
+                self.base
+                    .key_at(position)
+                    .map(|key_code| Keystroke::typed(key_code, held_keys))
+            })
+    }
+}
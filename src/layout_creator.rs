@@ -1,9 +1,13 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
     time::Instant,
 };
 
-use crate::keyboard::{KeyCode, KeyboardLayout};
+use crate::{
+    hungarian::minimum_cost_assignment,
+    keyboard::{KeyCode, KeyboardLayout},
+};
 
 pub trait LayoutHint: Sync + Send {
     /// Updates the internal state of the layout hint with the given key press.
@@ -15,90 +19,480 @@ pub trait LayoutHint: Sync + Send {
     fn rank_keys_for_position(&self, position: (usize, usize)) -> BTreeMap<KeyCode, f64>;
 }
 
+/// Lets a hint be shared with the rest of the program (for saving it, for example) while it is also part of a `LayoutCreator`.
+impl<Hint: LayoutHint> LayoutHint for Arc<Mutex<Hint>> {
+    fn receive_key_press(&mut self, key_code: KeyCode, time: Instant) {
+        self.lock().unwrap().receive_key_press(key_code, time);
+    }
+
+    fn rank_keys_for_position(&self, position: (usize, usize)) -> BTreeMap<KeyCode, f64> {
+        self.lock().unwrap().rank_keys_for_position(position)
+    }
+}
+
+/// How `LayoutCreator` turns the rankings into a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AssignmentStrategy {
+    /// Repeatedly locks in the highest rank that is left. Quick, but an early pick can take a position that another key needed more.
+    #[default]
+    Greedy,
+    /// Finds the layout with the highest possible `score_layout` using the Hungarian algorithm.
+    Hungarian,
+}
+
+/// How a hint's ranks are rescaled before they are added to the others', so that hints with big ranks don't drown out the rest.
+///
+/// The ranks are rescaled across every position at once, so a key still ranks higher at the positions the hint prefers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// Uses the ranks as they are.
+    #[default]
+    None,
+    /// Scales the ranks so the lowest is 0 and the highest is 1.
+    MinMax,
+    /// Subtracts the mean and divides by the standard deviation.
+    ZScore,
+    /// Replaces each rank by the share of ranks below it, so only the order matters.
+    RankOrder,
+}
+
+impl Normalization {
+    /// Rescales the ranks for a set of positions together.
+    fn apply(self, rankings: &mut [BTreeMap<KeyCode, f64>]) {
+        let mut ranks: Vec<f64> = rankings
+            .iter()
+            .flat_map(|rankings| rankings.values().copied())
+            .collect();
+        if ranks.is_empty() {
+            return;
+        }
+        let rescale: Box<dyn Fn(f64) -> f64> = match self {
+            Normalization::None => return,
+            Normalization::MinMax => {
+                let min = ranks.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = ranks.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                if max == min {
+                    Box::new(|_| 0.0)
+                } else {
+                    Box::new(move |rank| (rank - min) / (max - min))
+                }
+            }
+            Normalization::ZScore => {
+                let mean = ranks.iter().sum::<f64>() / ranks.len() as f64;
+                let variance = ranks.iter().map(|rank| (rank - mean).powi(2)).sum::<f64>()
+                    / ranks.len() as f64;
+                let standard_deviation = variance.sqrt();
+                if standard_deviation == 0.0 {
+                    Box::new(|_| 0.0)
+                } else {
+                    Box::new(move |rank| (rank - mean) / standard_deviation)
+                }
+            }
+            Normalization::RankOrder => {
+                ranks.sort_by(f64::total_cmp);
+                let count = ranks.len() as f64;
+                // Equal ranks all get the share of ranks strictly below them, so ties stay tied.
+                Box::new(move |rank| ranks.partition_point(|&other| other < rank) as f64 / count)
+            }
+        };
+        for rankings in rankings {
+            for rank in rankings.values_mut() {
+                *rank = rescale(*rank);
+            }
+        }
+    }
+}
+
+struct WeightedHint {
+    name: String,
+    hint: Box<dyn LayoutHint>,
+    weight: f64,
+    normalization: Normalization,
+}
+
+/// How much one hint added to a key's rank at a position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HintContribution {
+    pub name: String,
+    /// The rank the hint itself gave, before normalization and weighting.
+    pub raw_rank: f64,
+    pub normalized_rank: f64,
+    pub weight: f64,
+}
+
+impl HintContribution {
+    /// What this hint adds to the key's rank.
+    pub fn contribution(&self) -> f64 {
+        self.normalized_rank * self.weight
+    }
+}
+
 pub struct LayoutCreator {
-    layout_hints: Vec<Box<dyn LayoutHint>>,
+    layout_hints: Vec<WeightedHint>,
+    strategy: AssignmentStrategy,
+    /// The positions to fill, and the keys to fill them with.
+    template: KeyboardLayout,
 }
 
 impl LayoutCreator {
+    /// Creates a layout creator which adds up the hints' ranks as they are. The hints are named "hint 1", "hint 2" and so on.
     pub fn new(layout_hints: Vec<Box<dyn LayoutHint>>) -> Self {
-        Self { layout_hints }
+        let mut layout_creator = Self {
+            layout_hints: Vec::new(),
+            strategy: AssignmentStrategy::default(),
+            template: KeyboardLayout::qwerty(),
+        };
+        for (index, hint) in layout_hints.into_iter().enumerate() {
+            layout_creator.add_hint(
+                &format!("hint {}", index + 1),
+                hint,
+                1.0,
+                Normalization::None,
+            );
+        }
+        layout_creator
     }
 
-    pub fn create_layout(&self) -> KeyboardLayout {
-        // Step 1: find the rankings for every position.
-        let mut rankings = Vec::new();
-        for row in 0..3 {
-            let mut row_rankings = Vec::new();
-            for column in 0..10 {
-                let rankings = self.rank_keys_for_position((row, column));
-                row_rankings.push(rankings);
+    pub fn add_hint(
+        &mut self,
+        name: &str,
+        hint: Box<dyn LayoutHint>,
+        weight: f64,
+        normalization: Normalization,
+    ) {
+        self.layout_hints.push(WeightedHint {
+            name: name.to_string(),
+            hint,
+            weight,
+            normalization,
+        });
+    }
+
+    /// Changes the weight of the hint with the given name. Returns false if there is no such hint.
+    pub fn set_weight(&mut self, name: &str, weight: f64) -> bool {
+        match self.layout_hints.iter_mut().find(|hint| hint.name == name) {
+            Some(hint) => {
+                hint.weight = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes the normalization of the hint with the given name. Returns false if there is no such hint.
+    pub fn set_normalization(&mut self, name: &str, normalization: Normalization) -> bool {
+        match self.layout_hints.iter_mut().find(|hint| hint.name == name) {
+            Some(hint) => {
+                hint.normalization = normalization;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn hint_names(&self) -> impl Iterator<Item = &str> {
+        self.layout_hints.iter().map(|hint| hint.name.as_str())
+    }
+
+    /// The normalized ranks that a hint gives at each of `positions`. Only the template's keys are ranked, since hints can know
+    /// about keys (like Space) which aren't being placed, and those shouldn't affect the normalization. Ranks which aren't
+    /// finite are left out too, since they would turn the normalization and the Hungarian algorithm's costs into NaN.
+    fn normalized_rankings(
+        &self,
+        hint: &WeightedHint,
+        positions: &[(usize, usize)],
+    ) -> Vec<BTreeMap<KeyCode, f64>> {
+        let keys: Vec<KeyCode> = self.template.iter().collect();
+        let mut rankings: Vec<_> = positions
+            .iter()
+            .map(|&position| {
+                let mut rankings = hint.hint.rank_keys_for_position(position);
+                rankings.retain(|key, rank| keys.contains(key) && rank.is_finite());
+                rankings
+            })
+            .collect();
+        hint.normalization.apply(&mut rankings);
+        rankings
+    }
+
+    /// The template's positions, with `position` added to the end if it isn't one of them. Ranks are normalized across all
+    /// the positions, so a position outside the template is ranked as if it was part of it.
+    fn positions_with(&self, position: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut positions: Vec<_> = self.template.positions().collect();
+        if !positions.contains(&position) {
+            positions.push(position);
+        }
+        positions
+    }
+
+    /// What each hint adds to the rank of `key` at `position`, to see why a key ended up where it did.
+    pub fn contributions(&self, position: (usize, usize), key: KeyCode) -> Vec<HintContribution> {
+        let positions = self.positions_with(position);
+        let index = positions
+            .iter()
+            .position(|&other| other == position)
+            .unwrap();
+        self.layout_hints
+            .iter()
+            .map(|hint| HintContribution {
+                name: hint.name.clone(),
+                raw_rank: hint
+                    .hint
+                    .rank_keys_for_position(position)
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(0.0),
+                normalized_rank: self.normalized_rankings(hint, &positions)[index]
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(0.0),
+                weight: hint.weight,
+            })
+            .collect()
+    }
+
+    /// The combined ranks at each of `positions`.
+    fn all_rankings(&self, positions: &[(usize, usize)]) -> Vec<BTreeMap<KeyCode, f64>> {
+        let mut all_rankings = vec![BTreeMap::new(); positions.len()];
+        for hint in &self.layout_hints {
+            for (rankings, hint_rankings) in all_rankings
+                .iter_mut()
+                .zip(self.normalized_rankings(hint, positions))
+            {
+                for (key, rank) in hint_rankings {
+                    *rankings.entry(key).or_insert(0.0) += rank * hint.weight;
+                }
             }
-            rankings.push(row_rankings);
         }
+        all_rankings
+    }
+
+    pub fn strategy(&self) -> AssignmentStrategy {
+        self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: AssignmentStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// The layout whose positions `create_layout` fills, and whose keys it fills them with. This is QWERTY by default.
+    pub fn template(&self) -> &KeyboardLayout {
+        &self.template
+    }
+
+    /// Changes which positions are filled and which keys go in them, for example to include the number row or thumb keys.
+    pub fn set_template(&mut self, template: KeyboardLayout) {
+        self.template = template;
+    }
+
+    pub fn create_layout(&self) -> KeyboardLayout {
+        match self.strategy {
+            AssignmentStrategy::Greedy => self.create_layout_greedily(),
+            AssignmentStrategy::Hungarian => self.create_optimal_layout(),
+        }
+    }
+
+    fn create_layout_greedily(&self) -> KeyboardLayout {
+        // Step 1: find the rankings for every position.
+        let positions: Vec<_> = self.template.positions().collect();
+        let keys: Vec<_> = self.template.iter().collect();
+        let rankings = self.all_rankings(&positions);
 
         // Step 2: find the highest overall rank and lock it in. Repeat this until all positions are allocated.
-        let mut layout = KeyboardLayout::default();
+        let mut layout = KeyboardLayout::new(BTreeMap::new());
         let mut used_positions = BTreeSet::new();
         let mut used_keys = BTreeSet::new();
-        for _ in 0..30 {
-            let mut highest_rank = 0.0;
+        for _ in 0..positions.len() {
+            // Normalized ranks can be negative, so any rank beats none at all.
+            let mut highest_rank = f64::NEG_INFINITY;
             let mut best_position = None;
             let mut best_key = None;
-            for row in 0..3 {
-                for column in 0..10 {
-                    if used_positions.contains(&(row, column)) {
+            for (&position, position_rankings) in positions.iter().zip(&rankings) {
+                if used_positions.contains(&position) {
+                    continue;
+                }
+                for (key, rank) in position_rankings {
+                    if used_keys.contains(key) || !keys.contains(key) {
                         continue;
                     }
-                    for (key, rank) in &rankings[row][column] {
-                        if used_keys.contains(key) {
-                            continue;
-                        }
-                        if *rank > highest_rank {
-                            highest_rank = *rank;
-                            best_position = Some((row, column));
-                            best_key = Some(*key);
-                        }
+                    if *rank > highest_rank {
+                        highest_rank = *rank;
+                        best_position = Some(position);
+                        best_key = Some(*key);
                     }
                 }
             }
             // If the key wasn't found, put it in the first available position.
             let (row, column) = best_position.unwrap_or_else(|| {
-                for position in 0..30 {
-                    let position = (position / 10, position % 10);
-                    if !used_positions.contains(&position) {
-                        return position;
-                    }
-                }
-                unreachable!();
+                *positions
+                    .iter()
+                    .find(|position| !used_positions.contains(*position))
+                    .unwrap()
             });
-            let key = best_key.unwrap_or(
-                KeyboardLayout::QWERTY
+            let key = best_key.unwrap_or_else(|| {
+                self.template
                     .iter()
                     .find(|key_code| !used_keys.contains(key_code))
-                    .unwrap(),
-            );
+                    .unwrap()
+            });
             layout.set_key_at(row, column, key);
             used_positions.insert((row, column));
             used_keys.insert(key);
         }
         layout
     }
+
+    fn create_optimal_layout(&self) -> KeyboardLayout {
+        // The rank of a key at a position doesn't depend on where the other keys go, so this is a plain assignment problem.
+        // Ranks are negated since the algorithm looks for the lowest cost.
+        let positions: Vec<_> = self.template.positions().collect();
+        let keys: Vec<KeyCode> = self.template.iter().collect();
+        let costs: Vec<Vec<f64>> = self
+            .all_rankings(&positions)
+            .into_iter()
+            .map(|rankings| {
+                keys.iter()
+                    .map(|key| {
+                        // A huge weight can still make the combined rank infinite.
+                        -rankings
+                            .get(key)
+                            .copied()
+                            .filter(|rank| rank.is_finite())
+                            .unwrap_or(0.0)
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut layout = KeyboardLayout::new(BTreeMap::new());
+        for ((row, column), key_index) in positions.into_iter().zip(minimum_cost_assignment(&costs))
+        {
+            layout.set_key_at(row, column, keys[key_index]);
+        }
+        layout
+    }
+
+    /// Adds up the rank of every key in the layout for the position it is in. This is what `create_layout` tries to maximise.
+    pub fn score_layout(&self, layout: &KeyboardLayout) -> f64 {
+        let positions: Vec<_> = layout.positions().collect();
+        let mut score = 0.0;
+        for (key, rankings) in layout.iter().zip(self.all_rankings(&positions)) {
+            score += rankings.get(&key).copied().unwrap_or(0.0);
+        }
+        score
+    }
 }
 
 impl LayoutHint for LayoutCreator {
     fn rank_keys_for_position(&self, position: (usize, usize)) -> BTreeMap<KeyCode, f64> {
-        let mut key_rankings = BTreeMap::new();
-        for hint in &self.layout_hints {
-            for (key, rank) in hint.rank_keys_for_position(position) {
-                *key_rankings.entry(key).or_insert(0.0) += rank;
-            }
-        }
-        key_rankings
+        let positions = self.positions_with(position);
+        let index = positions
+            .iter()
+            .position(|&other| other == position)
+            .unwrap();
+        self.all_rankings(&positions).swap_remove(index)
     }
 
     fn receive_key_press(&mut self, key_code: KeyCode, time: Instant) {
         for hint in &mut self.layout_hints {
-            hint.receive_key_press(key_code, time);
+            hint.hint.receive_key_press(key_code, time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    /// A hint with a fixed rank for every key at every position.
+    struct FixedRanks(BTreeMap<(usize, usize), BTreeMap<KeyCode, f64>>);
+
+    impl LayoutHint for FixedRanks {
+        fn receive_key_press(&mut self, _key_code: KeyCode, _time: Instant) {}
+
+        fn rank_keys_for_position(&self, position: (usize, usize)) -> BTreeMap<KeyCode, f64> {
+            self.0.get(&position).cloned().unwrap_or_default()
+        }
+    }
+
+    fn random_ranks(seed: u64, template: &KeyboardLayout) -> FixedRanks {
+        let mut random = Random::new(seed);
+        FixedRanks(
+            template
+                .positions()
+                .map(|position| {
+                    let mut ranks = BTreeMap::new();
+                    for key in template.iter() {
+                        // Leave some ranks out, like hints without data for every key do.
+                        if random.below(5) != 0 {
+                            ranks.insert(key, random.fraction() * 100.0);
+                        }
+                    }
+                    (position, ranks)
+                })
+                .collect(),
+        )
+    }
+
+    fn score_with(layout_creator: &mut LayoutCreator, strategy: AssignmentStrategy) -> f64 {
+        layout_creator.set_strategy(strategy);
+        let layout = layout_creator.create_layout();
+        let mut keys: Vec<_> = layout.iter().collect();
+        keys.sort();
+        let mut template_keys: Vec<_> = layout_creator.template().iter().collect();
+        template_keys.sort();
+        assert_eq!(keys, template_keys);
+        layout_creator.score_layout(&layout)
+    }
+
+    #[test]
+    fn hungarian_never_scores_below_greedy() {
+        for seed in 0..20 {
+            let mut layout_creator = LayoutCreator::new(vec![Box::new(random_ranks(
+                seed,
+                &KeyboardLayout::qwerty(),
+            ))]);
+            let greedy = score_with(&mut layout_creator, AssignmentStrategy::Greedy);
+            let hungarian = score_with(&mut layout_creator, AssignmentStrategy::Hungarian);
+            assert!(
+                hungarian >= greedy - 1e-9,
+                "seed {seed}: hungarian {hungarian} < greedy {greedy}"
+            );
+        }
+    }
+
+    #[test]
+    fn infinite_ranks_are_left_out() {
+        let mut ranks = random_ranks(0, &KeyboardLayout::qwerty());
+        let first_position = (0, 0);
+        ranks
+            .0
+            .get_mut(&first_position)
+            .unwrap()
+            .insert(KeyCode::E, f64::INFINITY);
+        ranks
+            .0
+            .get_mut(&(1, 1))
+            .unwrap()
+            .insert(KeyCode::T, f64::NAN);
+        let mut layout_creator = LayoutCreator::new(vec![Box::new(ranks)]);
+        for strategy in [AssignmentStrategy::Greedy, AssignmentStrategy::Hungarian] {
+            assert!(score_with(&mut layout_creator, strategy).is_finite());
+        }
+        layout_creator.set_normalization("hint 1", Normalization::MinMax);
+        assert!(score_with(&mut layout_creator, AssignmentStrategy::Hungarian).is_finite());
+    }
+
+    #[test]
+    fn layouts_fill_the_template() {
+        let template = KeyboardLayout::qwerty_main_block();
+        let mut layout_creator = LayoutCreator::new(vec![Box::new(random_ranks(0, &template))]);
+        layout_creator.set_template(template.clone());
+        for strategy in [AssignmentStrategy::Greedy, AssignmentStrategy::Hungarian] {
+            layout_creator.set_strategy(strategy);
+            let layout = layout_creator.create_layout();
+            assert!(layout.positions().eq(template.positions()));
+            score_with(&mut layout_creator, strategy);
         }
     }
 }
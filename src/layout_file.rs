@@ -0,0 +1,727 @@
+//! A plain text format for saving layouts.
+//!
+//! A layout file has a row of keys on each line, separated by whitespace, with `_` for a position without a key. Keys are written
+//! the way `KeyCode` shows them (`Q`, `;`, `Space`...), ignoring case. Before the rows there can be lines like `name: Colemak`,
+//! for details about the layout. Blank lines are ignored, so a row without any keys is written as a single `_`.
+//!
+//! ```text
+//! name: Colemak-DH
+//!
+//! Q W F P B J L U Y ;
+//! A R S T G M N E I O
+//! Z X C D V K H , . /
+//! ```
+//!
+//! The lines are rows 0, 1, 2 and so on, but a line can start with another row number followed by `|`, and the lines after it
+//! carry on from there. Layouts of the whole main block are written this way, so that the number row (row 3, see
+//! `KeyCode::position`) comes above the letters:
+//!
+//! ```text
+//! 3| 1 2 3 4 5 6 7 8 9 0 - =
+//! 0| Q W E R T Y U I O P [ ] \
+//! ```
+//!
+//! After the base layer's rows there can be a section for each of the other layers (see `LayeredLayout`), which starts with a
+//! line giving the layer's name and its activation keys. A layer's rows have keystrokes the way `Keystroke` shows them, and
+//! are numbered from 0 again. Files without any layer sections get the usual empty Shift and AltGr layers.
+//!
+//! ```text
+//! Symbols (CapsLock):
+//! ! @ # $ % ^ & * ( )
+//! ```
+//!
+//! A file with a `characters` detail naming a system layout (`us`, `qwertz` or `azerty`, see `CharacterMap`) has the
+//! characters that each position types instead, so that it can hold anything that system layout can type. The base rows can
+//! be followed by `Shift:` and `AltGr:` sections. A character which would be read as something else is written after a `\`
+//! (like `\_`), and the ones which can't be seen as `Space`, `Enter` and `Tab`. Keys which don't type anything, like the
+//! modifiers, can't be in these files, and neither can other layers. A layout with any of those is written with keys
+//! instead, leaving out the `characters` detail.
+//!
+//! ```text
+//! characters: qwertz
+//!
+//! q w e r t z u i o p ü
+//!
+//! Shift:
+//! Q W E R T Z U I O P Ü
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    characters::{CharacterLayout, CharacterMap, ShiftLevel},
+    keyboard::{KeyCode, KeyboardLayout, Keystroke},
+    layers::{Layer, LayeredLayout},
+};
+
+/// The lines which start the sections of a characters file after the base one.
+const LEVEL_HEADERS: [(ShiftLevel, &str); 2] =
+    [(ShiftLevel::Shift, "Shift:"), (ShiftLevel::AltGr, "AltGr:")];
+/// The characters which are written by name in a characters file.
+const CHARACTER_NAMES: [(char, &str); 3] = [(' ', "Space"), ('\n', "Enter"), ('\t', "Tab")];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseLayoutError {
+    /// A line (counting from 1) has something which isn't a key, or a keystroke in a layer.
+    UnknownKey { line: usize, key: String },
+    /// A key is on two lines (counting from 1), or twice on the same one.
+    DuplicateKey {
+        key_code: KeyCode,
+        first_line: usize,
+        second_line: usize,
+    },
+    /// The layout doesn't have all the letters, so it couldn't type most text.
+    MissingLetters(Vec<KeyCode>),
+    /// A row is on two lines (counting from 1).
+    DuplicateRow {
+        row: usize,
+        first_line: usize,
+        second_line: usize,
+    },
+    /// The `characters` detail isn't a system layout we know.
+    UnknownSystemLayout(String),
+    /// A character is in the base rows twice, on these lines (counting from 1).
+    DuplicateCharacter {
+        character: char,
+        first_line: usize,
+        second_line: usize,
+    },
+    /// A line (counting from 1) has a character which the system layout can't type.
+    UntypeableCharacter { line: usize, character: char },
+}
+
+impl Display for ParseLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseLayoutError::UnknownKey { line, key } => {
+                write!(f, "Line {line}: unknown key '{key}'")
+            }
+            ParseLayoutError::DuplicateKey {
+                key_code,
+                first_line,
+                second_line,
+            } if first_line == second_line => {
+                write!(f, "Line {first_line}: {key_code} is in the row twice")
+            }
+            ParseLayoutError::DuplicateKey {
+                key_code,
+                first_line,
+                second_line,
+            } => write!(
+                f,
+                "Line {second_line}: {key_code} is already on line {first_line}"
+            ),
+            ParseLayoutError::MissingLetters(letters) => {
+                let letters: Vec<String> =
+                    letters.iter().map(|letter| letter.to_string()).collect();
+                write!(f, "The layout has no {}", letters.join(", "))
+            }
+            ParseLayoutError::DuplicateRow {
+                row,
+                first_line,
+                second_line,
+            } => write!(
+                f,
+                "Line {second_line}: row {row} is already on line {first_line}"
+            ),
+            ParseLayoutError::UnknownSystemLayout(name) => {
+                write!(f, "Unknown system layout '{name}'")
+            }
+            ParseLayoutError::DuplicateCharacter {
+                character,
+                first_line,
+                second_line,
+            } => write!(
+                f,
+                "Line {second_line}: '{character}' is already on line {first_line}"
+            ),
+            ParseLayoutError::UntypeableCharacter { line, character } => write!(
+                f,
+                "Line {line}: '{character}' can't be typed with the system layout"
+            ),
+        }
+    }
+}
+
+impl Error for ParseLayoutError {}
+
+/// A layout along with the details from the top of its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutFile {
+    /// The details as name and value pairs, in the order they are written in.
+    pub metadata: Vec<(String, String)>,
+    pub layout: LayeredLayout,
+}
+
+/// The row number at the start of a line like `3| 1 2 3`, and the rest of the line.
+fn parse_row_number(line: &str) -> Option<(usize, &str)> {
+    let (row, rest) = line.split_once('|')?;
+    Some((row.parse().ok()?, rest))
+}
+
+/// Where a row is written in a layout file: as it is on the keyboard, from the number row down (see
+/// `KeyCode::physical_row`). The keys left of the letters (row 5) are really a column, so they go last.
+fn row_order(row: usize) -> (bool, usize) {
+    (row == 5, KeyCode::physical_row((row, 0)))
+}
+
+/// Keeps track of which row each line of a section is.
+#[derive(Default)]
+struct RowNumbers {
+    next_row: usize,
+    /// The line that each row was found on, to point at both places if it turns up again.
+    lines: BTreeMap<usize, usize>,
+}
+
+impl RowNumbers {
+    /// The row of the line, along with the line without its row number.
+    fn row<'a>(
+        &mut self,
+        line: &'a str,
+        line_number: usize,
+    ) -> Result<(usize, &'a str), ParseLayoutError> {
+        let (row, line) = parse_row_number(line).unwrap_or((self.next_row, line));
+        if let Some(&first_line) = self.lines.get(&row) {
+            return Err(ParseLayoutError::DuplicateRow {
+                row,
+                first_line,
+                second_line: line_number,
+            });
+        }
+        self.lines.insert(row, line_number);
+        self.next_row = row + 1;
+        Ok((row, line))
+    }
+}
+
+/// The character that a cell of a characters file stands for.
+fn parse_character(cell: &str) -> Option<char> {
+    if let Some(&(character, _)) = CHARACTER_NAMES
+        .iter()
+        .find(|(_, name)| cell.eq_ignore_ascii_case(name))
+    {
+        return Some(character);
+    }
+    let cell = cell
+        .strip_prefix('\\')
+        .filter(|rest| !rest.is_empty())
+        .unwrap_or(cell);
+    let mut characters = cell.chars();
+    match (characters.next(), characters.next()) {
+        (Some(character), None) => Some(character),
+        _ => None,
+    }
+}
+
+/// How a character is written in a characters file, the other way around from `parse_character`.
+fn format_character(character: char) -> String {
+    match CHARACTER_NAMES
+        .iter()
+        .find(|&&(other, _)| other == character)
+    {
+        Some((_, name)) => name.to_string(),
+        None if character == '_' => "\\_".to_string(),
+        None => character.to_string(),
+    }
+}
+
+/// The layer that a line like `Symbols (CapsLock):` starts a section for, or `None` if the line is a row.
+fn parse_layer_header(line: &str, line_number: usize) -> Result<Option<Layer>, ParseLayoutError> {
+    let Some((name, activation_keys)) = line
+        .strip_suffix("):")
+        .and_then(|header| header.rsplit_once('('))
+    else {
+        return Ok(None);
+    };
+    let activation_keys = activation_keys
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            key.parse().map_err(|_| ParseLayoutError::UnknownKey {
+                line: line_number,
+                key: key.to_string(),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Some(Layer::new(name.trim(), activation_keys)))
+}
+
+/// Whether the line is a detail like `name: Colemak` rather than a row. No key's name ends in `:` apart from `:` itself.
+fn parse_metadata(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.split_once(':')?;
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || "-_".contains(character));
+    is_name.then(|| (name, value.trim()))
+}
+
+impl LayoutFile {
+    pub fn new(layout: impl Into<LayeredLayout>) -> Self {
+        Self {
+            metadata: Vec::new(),
+            layout: layout.into(),
+        }
+    }
+
+    /// The value of a detail, found by its name ignoring case.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a detail, replacing any value it had.
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self
+            .metadata
+            .iter_mut()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+        {
+            Some((_, old_value)) => *old_value = value,
+            None => self.metadata.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+/// Reads the rows of a file with keys in them, and the layers after them.
+fn parse_keys(rows: &[(usize, &str)]) -> Result<LayeredLayout, ParseLayoutError> {
+    let mut base = KeyboardLayout::new(BTreeMap::new());
+    let mut layers: Vec<Layer> = Vec::new();
+    // The line that each base key was found on, to point at both places if it turns up again.
+    let mut lines = BTreeMap::new();
+    let mut row_numbers = RowNumbers::default();
+    for &(line_number, line) in rows {
+        if let Some(layer) = parse_layer_header(line, line_number)? {
+            layers.push(layer);
+            row_numbers = RowNumbers::default();
+            continue;
+        }
+        let (row_index, line) = row_numbers.row(line, line_number)?;
+        for (column_index, key) in line.split_whitespace().enumerate() {
+            if key == "_" {
+                continue;
+            }
+            let unknown_key = |_| ParseLayoutError::UnknownKey {
+                line: line_number,
+                key: key.to_string(),
+            };
+            if let Some(layer) = layers.last_mut() {
+                let keystroke: Keystroke = key.parse().map_err(unknown_key)?;
+                layer.set_keystroke_at((row_index, column_index), keystroke);
+                continue;
+            }
+            let key_code: KeyCode = key.parse().map_err(unknown_key)?;
+            if let Some(&first_line) = lines.get(&key_code) {
+                return Err(ParseLayoutError::DuplicateKey {
+                    key_code,
+                    first_line,
+                    second_line: line_number,
+                });
+            }
+            lines.insert(key_code, line_number);
+            base.set_key_at(row_index, column_index, key_code);
+        }
+    }
+    let missing_letters: Vec<KeyCode> = ('a'..='z')
+        .filter_map(KeyCode::from_character)
+        .filter(|key_code| !lines.contains_key(key_code))
+        .collect();
+    if !missing_letters.is_empty() {
+        return Err(ParseLayoutError::MissingLetters(missing_letters));
+    }
+    Ok(if layers.is_empty() {
+        LayeredLayout::new(base)
+    } else {
+        LayeredLayout::with_layers(base, layers)
+    })
+}
+
+/// Reads the rows of a characters file, and turns them into the keystrokes which type them on the system layout.
+fn parse_characters(
+    rows: &[(usize, &str)],
+    character_map: &CharacterMap,
+) -> Result<LayeredLayout, ParseLayoutError> {
+    let mut characters = CharacterLayout::default();
+    let mut level = ShiftLevel::Base;
+    // The line that each base character was found on.
+    let mut lines = BTreeMap::new();
+    let mut row_numbers = RowNumbers::default();
+    for &(line_number, line) in rows {
+        if let Some(&(other_level, _)) = LEVEL_HEADERS
+            .iter()
+            .find(|(_, header)| line.eq_ignore_ascii_case(header))
+        {
+            level = other_level;
+            row_numbers = RowNumbers::default();
+            continue;
+        }
+        let (row_index, line) = row_numbers.row(line, line_number)?;
+        for (column_index, cell) in line.split_whitespace().enumerate() {
+            if cell == "_" {
+                continue;
+            }
+            let character = parse_character(cell).ok_or_else(|| ParseLayoutError::UnknownKey {
+                line: line_number,
+                key: cell.to_string(),
+            })?;
+            if character_map.keystroke_for(character).is_none() {
+                return Err(ParseLayoutError::UntypeableCharacter {
+                    line: line_number,
+                    character,
+                });
+            }
+            if level == ShiftLevel::Base {
+                if let Some(&first_line) = lines.get(&character) {
+                    return Err(ParseLayoutError::DuplicateCharacter {
+                        character,
+                        first_line,
+                        second_line: line_number,
+                    });
+                }
+                lines.insert(character, line_number);
+            }
+            characters.set_character_at(level, (row_index, column_index), character);
+        }
+    }
+    let missing_letters: Vec<KeyCode> = ('a'..='z')
+        .filter(|letter| !lines.contains_key(letter))
+        .filter_map(KeyCode::from_character)
+        .collect();
+    if !missing_letters.is_empty() {
+        return Err(ParseLayoutError::MissingLetters(missing_letters));
+    }
+    // Every character has been checked to be typeable already.
+    Ok(characters.to_layered(character_map).unwrap())
+}
+
+impl LayoutFile {
+    /// The system layout named by the `characters` detail, if the file has characters instead of keys.
+    pub fn character_map(&self) -> Result<Option<CharacterMap>, ParseLayoutError> {
+        self.get("characters")
+            .map(|name| {
+                CharacterMap::from_name(name)
+                    .ok_or_else(|| ParseLayoutError::UnknownSystemLayout(name.to_string()))
+            })
+            .transpose()
+    }
+}
+
+impl FromStr for LayoutFile {
+    type Err = ParseLayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut file = Self::new(KeyboardLayout::new(BTreeMap::new()));
+        // The lines after the details, along with their numbers.
+        let mut rows = Vec::new();
+        for (line_index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if rows.is_empty() {
+                if let Some((name, value)) = parse_metadata(line) {
+                    file.metadata.push((name.to_string(), value.to_string()));
+                    continue;
+                }
+            }
+            rows.push((line_index + 1, line));
+        }
+        file.layout = match file.character_map()? {
+            Some(character_map) => parse_characters(&rows, &character_map)?,
+            None => parse_keys(&rows)?,
+        };
+        Ok(file)
+    }
+}
+
+/// Whether the two layouts type the same everywhere, with nothing held or with any one of their activation keys held.
+fn type_the_same(layout: &LayeredLayout, other: &LayeredLayout) -> bool {
+    let mut positions = BTreeSet::new();
+    let mut held_keys = vec![BTreeSet::new()];
+    for layout in [layout, other] {
+        positions.extend(layout.base().positions());
+        for layer in layout.layers() {
+            positions.extend(layer.keystrokes().map(|(position, _)| position));
+            held_keys.extend(
+                layer
+                    .activation_keys()
+                    .iter()
+                    .map(|&key_code| BTreeSet::from([key_code])),
+            );
+        }
+    }
+    held_keys.iter().all(|held_keys| {
+        positions.iter().all(|&position| {
+            layout.keystroke_at(position, held_keys) == other.keystroke_at(position, held_keys)
+        })
+    })
+}
+
+impl Display for LayoutFile {
+    /// Writes the file, with characters instead of keys if the `characters` detail names a system layout we know and the
+    /// characters describe the whole layout. Otherwise the `characters` detail is left out, so that the file can be read
+    /// back.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let characters = self
+            .character_map()
+            .ok()
+            .flatten()
+            .and_then(|character_map| {
+                let characters = CharacterLayout::from_layered(&self.layout, &character_map);
+                let typed = characters.to_layered(&character_map).ok()?;
+                type_the_same(&self.layout, &typed).then_some(characters)
+            });
+        let metadata = self
+            .metadata
+            .iter()
+            .filter(|(name, _)| characters.is_some() || !name.eq_ignore_ascii_case("characters"))
+            .collect::<Vec<_>>();
+        for (name, value) in &metadata {
+            writeln!(f, "{name}: {value}")?;
+        }
+        if !metadata.is_empty() {
+            writeln!(f)?;
+        }
+        let Some(characters) = characters else {
+            return writeln!(f, "{}", self.layout);
+        };
+        let cells = |level| {
+            characters
+                .characters(level)
+                .map(|(position, character)| (position, format_character(character)))
+        };
+        write_rows(f, cells(ShiftLevel::Base))?;
+        for (level, header) in LEVEL_HEADERS {
+            if cells(level).next().is_some() {
+                write!(f, "\n\n{header}\n")?;
+                write_rows(f, cells(level))?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+impl FromStr for KeyboardLayout {
+    type Err = ParseLayoutError;
+
+    /// Parses a layout file, leaving out the details and the layers.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<LayoutFile>()
+            .map(|file| file.layout.base().clone())
+    }
+}
+
+/// Writes the rows of a layout file, without a newline at the end.
+fn write_rows<T: Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    keys: impl Iterator<Item = ((usize, usize), T)>,
+) -> std::fmt::Result {
+    let mut cells: BTreeMap<usize, BTreeMap<usize, T>> = BTreeMap::new();
+    for ((row, column), key) in keys {
+        cells.entry(row).or_default().insert(column, key);
+    }
+    let row_count = cells.keys().next_back().map_or(0, |row| row + 1);
+    let mut rows: Vec<usize> = (0..row_count).collect();
+    rows.sort_by_key(|&row| row_order(row));
+    for (index, &row_index) in rows.iter().enumerate() {
+        if index > 0 {
+            writeln!(f)?;
+        }
+        // Rows are only numbered where they don't follow on from the one before.
+        let previous_row = index.checked_sub(1).map(|index| rows[index]);
+        if previous_row.map_or(0, |row| row + 1) != row_index {
+            write!(f, "{row_index}| ")?;
+        }
+        let row = cells.get(&row_index);
+        let column_count = row
+            .and_then(|row| row.keys().next_back())
+            .map_or(1, |column| column + 1);
+        let keys: Vec<String> = (0..column_count)
+            .map(
+                |column_index| match row.and_then(|row| row.get(&column_index)) {
+                    Some(key) => key.to_string(),
+                    None => "_".to_string(),
+                },
+            )
+            .collect();
+        write!(f, "{}", keys.join(" "))?;
+    }
+    Ok(())
+}
+
+impl Display for KeyboardLayout {
+    /// Shows the rows of a layout file, without a newline at the end.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_rows(f, self.keys())
+    }
+}
+
+impl Display for LayeredLayout {
+    /// Shows the base layer's rows followed by a section for each layer, without a newline at the end. The sections are left out
+    /// when the layers are the usual empty Shift and AltGr ones.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.base())?;
+        if self.layers() == LayeredLayout::new(KeyboardLayout::new(BTreeMap::new())).layers() {
+            return Ok(());
+        }
+        for layer in self.layers() {
+            let activation_keys: Vec<String> = layer
+                .activation_keys()
+                .iter()
+                .map(|key_code| key_code.to_string())
+                .collect();
+            write!(f, "\n\n{} ({}):", layer.name(), activation_keys.join(", "))?;
+            if layer.keystrokes().next().is_some() {
+                writeln!(f)?;
+                write_rows(f, layer.keystrokes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_block_rows_are_in_keyboard_order() {
+        let layout = KeyboardLayout::qwerty_main_block();
+        let text = layout.to_string();
+        let first_keys: Vec<&str> = text
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(first_keys, ["3|", "0|", "A", "Z", "4|", "`"]);
+        assert_eq!(text.parse::<KeyboardLayout>().unwrap(), layout);
+    }
+
+    #[test]
+    fn letter_rows_are_not_numbered() {
+        let layout = KeyboardLayout::qwerty();
+        let text = layout.to_string();
+        assert!(text.starts_with("Q W E R T Y U I O P\nA S D"));
+        assert_eq!(text.parse::<KeyboardLayout>().unwrap(), layout);
+    }
+
+    #[test]
+    fn rows_can_only_be_given_once() {
+        let text = "Q W E R T Y U I O P\nA S D F G H J K L ;\nZ X C V B N M , . /\n1| [ ]";
+        assert_eq!(
+            text.parse::<KeyboardLayout>(),
+            Err(ParseLayoutError::DuplicateRow {
+                row: 1,
+                first_line: 2,
+                second_line: 4
+            })
+        );
+    }
+
+    #[test]
+    fn layers_are_saved_and_loaded() {
+        let mut layout = LayeredLayout::new(KeyboardLayout::qwerty());
+        let mut symbols = Layer::new("Symbols", vec![KeyCode::CapsLock]);
+        symbols.set_keystroke_at((0, 0), Keystroke::shifted(KeyCode::Digit1));
+        symbols.set_keystroke_at((1, 2), Keystroke::shifted(KeyCode::Backslash));
+        layout.add_layer(symbols);
+        let mut file = LayoutFile::new(layout.clone());
+        file.set("name", "With symbols");
+
+        let text = file.to_string();
+        assert!(text.ends_with("Symbols (CapsLock):\n!\n_ _ |\n"));
+        assert_eq!(text.parse::<LayoutFile>().unwrap(), file);
+    }
+
+    #[test]
+    fn files_without_layers_get_the_usual_ones() {
+        let text = KeyboardLayout::qwerty().to_string();
+        let file: LayoutFile = text.parse().unwrap();
+        assert_eq!(file.layout, LayeredLayout::new(KeyboardLayout::qwerty()));
+        assert_eq!(file.to_string(), text + "\n");
+    }
+
+    #[test]
+    fn characters_files_are_typed_on_their_system_layout() {
+        let text = "characters: qwertz\n\nq w e r t z u i o p ü\na s d f g h j k l ö ä\ny x c v b n m , . \\_\n\nShift:\nQ W\n";
+        let file: LayoutFile = text.parse().unwrap();
+        let base = file.layout.base();
+        // QWERTZ has z where QWERTY has y, and ü and ö where QWERTY has [ and ;.
+        assert_eq!(base.key_at((0, 5)), Some(KeyCode::Y));
+        assert_eq!(base.key_at((0, 10)), Some(KeyCode::LeftBracket));
+        assert_eq!(base.key_at((2, 0)), Some(KeyCode::Z));
+        assert_eq!(base.key_at((1, 9)), Some(KeyCode::Semicolon));
+        // _ is Shift and - on QWERTZ, so it goes on the layer for keystrokes with modifiers.
+        assert_eq!(
+            file.layout.keystroke_at((2, 9), &Default::default()),
+            Some(Keystroke::shifted(KeyCode::Slash))
+        );
+        // Everything that the positions type with Shift and AltGr is written out.
+        let written = file.to_string();
+        assert!(written.contains("Shift:\nQ W E R T Z U I O P Ü\n"));
+        assert!(written.contains("AltGr:\n@ _ €\n"));
+        assert_eq!(written.parse::<LayoutFile>().unwrap().to_string(), written);
+    }
+
+    #[test]
+    fn layouts_which_characters_cant_describe_are_saved_with_keys() {
+        let mut layout = LayeredLayout::new(KeyboardLayout::qwerty());
+        let mut symbols = Layer::new("Symbols", vec![KeyCode::CapsLock]);
+        symbols.set_keystroke_at((0, 0), Keystroke::shifted(KeyCode::Digit1));
+        layout.add_layer(symbols);
+        let mut file = LayoutFile::new(layout.clone());
+        file.set("characters", "us");
+        let text = file.to_string();
+        assert!(!text.contains("characters"));
+        assert_eq!(text.parse::<LayoutFile>().unwrap().layout, layout);
+
+        // The same goes for a system layout we don't know.
+        file.layout = LayeredLayout::new(KeyboardLayout::qwerty());
+        file.set("characters", "dvorak");
+        let reloaded: LayoutFile = file.to_string().parse().unwrap();
+        assert_eq!(reloaded.layout, file.layout);
+
+        // Without the layer, the characters are enough.
+        file.set("characters", "us");
+        let text = file.to_string();
+        assert!(text.starts_with("characters: us\n\nq w e r t y u i o p\n"));
+        let reloaded: LayoutFile = text.parse().unwrap();
+        assert!(type_the_same(&reloaded.layout, &file.layout));
+    }
+
+    #[test]
+    fn characters_must_be_typeable() {
+        let text = "characters: us\n\nq w e r t y u i o p ü";
+        assert_eq!(
+            text.parse::<LayoutFile>(),
+            Err(ParseLayoutError::UntypeableCharacter {
+                line: 3,
+                character: 'ü'
+            })
+        );
+        assert_eq!(
+            "characters: dvorak\n\nq".parse::<LayoutFile>(),
+            Err(ParseLayoutError::UnknownSystemLayout("dvorak".to_string()))
+        );
+    }
+}
@@ -0,0 +1,33 @@
+pub mod annealing;
+pub mod characters;
+pub mod corpus;
+pub mod digram_timing;
+pub mod evaluator;
+pub mod finger_map;
+pub mod genetic;
+pub mod geometry;
+pub mod gui;
+pub mod hungarian;
+pub mod json;
+pub mod key_event_source;
+pub mod keyboard;
+pub mod kle;
+pub mod layers;
+pub mod layout_creator;
+pub mod layout_file;
+pub mod presets;
+pub mod quadratic_assignment;
+pub mod random;
+pub mod remapper;
+pub mod session;
+pub mod transition_timing;
+
+#[cfg_attr(windows, path = "windows/input.rs")]
+#[cfg_attr(target_os = "linux", path = "linux/input.rs")]
+pub mod input;
+#[cfg_attr(windows, path = "windows/scancode.rs")]
+#[cfg_attr(target_os = "linux", path = "linux/scancode.rs")]
+pub mod scancode;
+#[cfg_attr(windows, path = "windows/trace.rs")]
+#[cfg_attr(target_os = "linux", path = "linux/trace.rs")]
+pub mod trace;
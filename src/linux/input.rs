@@ -0,0 +1,265 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    mem::size_of,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    slice,
+};
+
+use libc::{input_event, input_id, timeval, uinput_setup, O_NONBLOCK, UINPUT_MAX_NAME_SIZE};
+
+use crate::{
+    keyboard::KeyCode,
+    scancode::{scancode_of, BTN_MISC, EV_KEY, EV_SYN, KEY_PRESSED, KEY_RELEASED, SYN_REPORT},
+    session::KeyEventKind,
+};
+
+// ioctl request numbers from linux/uinput.h.
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_DEV_SETUP: libc::c_ulong = 0x405c5503;
+const UI_SET_EVBIT: libc::c_ulong = 0x40045564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x40045565;
+
+const BUS_VIRTUAL: u16 = 0x06;
+
+pub const VIRTUAL_KEYBOARD_NAME: &str = "Keyboard Layout Optimizer";
+
+/// Something that key events can be written to.
+///
+/// On a real system this is the uinput device, but anything that records the events will do.
+pub trait KeyEventEmitter: Send {
+    fn emit(&mut self, event_type: u16, code: u16, value: i32) -> io::Result<()>;
+}
+
+/// Keeps the events instead of sending them anywhere. Clones share the same events.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct RecordingEmitter(std::sync::Arc<std::sync::Mutex<Vec<(u16, u16, i32)>>>);
+
+#[cfg(test)]
+impl RecordingEmitter {
+    pub fn events(&self) -> Vec<(u16, u16, i32)> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl KeyEventEmitter for RecordingEmitter {
+    fn emit(&mut self, event_type: u16, code: u16, value: i32) -> io::Result<()> {
+        self.0.lock().unwrap().push((event_type, code, value));
+        Ok(())
+    }
+}
+
+/// A virtual keyboard created through `/dev/uinput`.
+pub struct VirtualKeyboard(File);
+
+impl VirtualKeyboard {
+    pub fn create() -> io::Result<Self> {
+        let device = OpenOptions::new()
+            .write(true)
+            .custom_flags(O_NONBLOCK)
+            .open("/dev/uinput")?;
+        let fd = device.as_raw_fd();
+        let mut setup = uinput_setup {
+            id: input_id {
+                bustype: BUS_VIRTUAL,
+                vendor: 0,
+                product: 0,
+                version: 1,
+            },
+            name: [0; UINPUT_MAX_NAME_SIZE],
+            ff_effects_max: 0,
+        };
+        for (destination, &byte) in setup.name.iter_mut().zip(VIRTUAL_KEYBOARD_NAME.as_bytes()) {
+            *destination = byte as libc::c_char;
+        }
+        unsafe {
+            check_ioctl(libc::ioctl(fd, UI_SET_EVBIT as _, EV_KEY as libc::c_int))?;
+            // We forward everything the physical keyboard sends, so we need to be able to send every key.
+            for scancode in 1..BTN_MISC {
+                check_ioctl(libc::ioctl(fd, UI_SET_KEYBIT as _, scancode as libc::c_int))?;
+            }
+            check_ioctl(libc::ioctl(
+                fd,
+                UI_DEV_SETUP as _,
+                &setup as *const uinput_setup,
+            ))?;
+            check_ioctl(libc::ioctl(fd, UI_DEV_CREATE as _))?;
+        }
+        Ok(Self(device))
+    }
+}
+
+impl KeyEventEmitter for VirtualKeyboard {
+    fn emit(&mut self, event_type: u16, code: u16, value: i32) -> io::Result<()> {
+        let event = input_event {
+            time: timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_: event_type,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                &event as *const input_event as *const u8,
+                size_of::<input_event>(),
+            )
+        };
+        self.0.write_all(bytes)
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::ioctl(self.0.as_raw_fd(), UI_DEV_DESTROY as _);
+        }
+    }
+}
+
+pub(crate) fn check_ioctl(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Emits a press or a release of the key, followed by a sync event.
+pub fn emit_key_event(
+    emitter: &mut dyn KeyEventEmitter,
+    code: KeyCode,
+    kind: KeyEventKind,
+) -> io::Result<()> {
+    let value = match kind {
+        KeyEventKind::Down => KEY_PRESSED,
+        KeyEventKind::Up => KEY_RELEASED,
+    };
+    emitter.emit(EV_KEY, scancode_of(code), value)?;
+    emitter.emit(EV_SYN, SYN_REPORT, 0)
+}
+
+/// Emits a full press and release of the key, including the sync events after each half.
+pub fn emit_key_stroke(emitter: &mut dyn KeyEventEmitter, code: KeyCode) -> io::Result<()> {
+    emit_key_event(emitter, code, KeyEventKind::Down)?;
+    emit_key_event(emitter, code, KeyEventKind::Up)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{
+        key_event_source::Subscribers,
+        keyboard::Keystroke,
+        scancode::{KEY_1, KEY_A, KEY_LEFTSHIFT, KEY_Q, KEY_RIGHTALT},
+    };
+
+    const SYN: (u16, u16, i32) = (EV_SYN, SYN_REPORT, 0);
+
+    fn down(scancode: u16) -> (u16, u16, i32) {
+        (EV_KEY, scancode, KEY_PRESSED)
+    }
+
+    fn up(scancode: u16) -> (u16, u16, i32) {
+        (EV_KEY, scancode, KEY_RELEASED)
+    }
+
+    /// What is emitted when a subscriber sends `keystroke` while `held_keys` are held.
+    fn emitted(keystroke: Keystroke, held_keys: &[KeyCode]) -> Vec<(u16, u16, i32)> {
+        let subscribers = Subscribers::default();
+        subscribers.subscribe(Box::new(move |context, _| {
+            context.suppress();
+            context.send_keystroke(keystroke);
+        }));
+        let held_keys: BTreeSet<_> = held_keys.iter().copied().collect();
+        let context = subscribers.dispatch(KeyCode::Z, KeyEventKind::Down, &held_keys);
+        let mut emitter = RecordingEmitter::default();
+        for (key_code, kind) in context.key_events() {
+            emit_key_event(&mut emitter, key_code, kind).unwrap();
+        }
+        // Only the key events matter here, the sync after each one is checked by `key_strokes_are_synced`.
+        let events = emitter.events();
+        assert!(events.iter().skip(1).step_by(2).all(|&event| event == SYN));
+        events.into_iter().step_by(2).collect()
+    }
+
+    #[test]
+    fn key_strokes_are_synced() {
+        let mut emitter = RecordingEmitter::default();
+        emit_key_stroke(&mut emitter, KeyCode::A).unwrap();
+        assert_eq!(emitter.events(), vec![down(KEY_A), SYN, up(KEY_A), SYN]);
+    }
+
+    #[test]
+    fn plain_keystrokes_are_sent_as_they_are() {
+        assert_eq!(
+            emitted(Keystroke::new(KeyCode::A), &[KeyCode::Z]),
+            vec![down(KEY_A), up(KEY_A)]
+        );
+    }
+
+    #[test]
+    fn shift_is_pressed_for_shifted_keystrokes() {
+        assert_eq!(
+            emitted(Keystroke::shifted(KeyCode::A), &[KeyCode::Z]),
+            vec![
+                down(KEY_LEFTSHIFT),
+                down(KEY_A),
+                up(KEY_A),
+                up(KEY_LEFTSHIFT)
+            ]
+        );
+    }
+
+    #[test]
+    fn held_shift_is_let_go_of_for_unshifted_keystrokes() {
+        assert_eq!(
+            emitted(
+                Keystroke::new(KeyCode::A),
+                &[KeyCode::LeftShift, KeyCode::Z]
+            ),
+            vec![
+                up(KEY_LEFTSHIFT),
+                down(KEY_A),
+                up(KEY_A),
+                down(KEY_LEFTSHIFT)
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_gr_is_pressed_for_alt_gr_keystrokes() {
+        let keystroke = Keystroke {
+            alt_gr: true,
+            ..Keystroke::new(KeyCode::Q)
+        };
+        assert_eq!(
+            emitted(keystroke, &[KeyCode::Z]),
+            vec![down(KEY_RIGHTALT), down(KEY_Q), up(KEY_Q), up(KEY_RIGHTALT)]
+        );
+    }
+
+    #[test]
+    fn held_alt_gr_is_swapped_for_shift() {
+        assert_eq!(
+            emitted(
+                Keystroke::shifted(KeyCode::Digit1),
+                &[KeyCode::RightAlt, KeyCode::Z]
+            ),
+            vec![
+                down(KEY_LEFTSHIFT),
+                up(KEY_RIGHTALT),
+                down(KEY_1),
+                up(KEY_1),
+                down(KEY_RIGHTALT),
+                up(KEY_LEFTSHIFT)
+            ]
+        );
+    }
+}
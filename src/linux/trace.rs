@@ -0,0 +1,421 @@
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fs::{self, File},
+    io::{self, Read},
+    mem::size_of,
+    os::fd::AsRawFd,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use libc::input_event;
+
+use crate::{
+    input::{check_ioctl, emit_key_event, emit_key_stroke, KeyEventEmitter, VirtualKeyboard},
+    key_event_source::{KeyEventCallback, KeyEventSource, Subscribers, SubscriptionId},
+    keyboard::KeyCode,
+    scancode::{key_code_from_scancode, EV_KEY, KEY_A, KEY_PRESSED, KEY_RELEASED},
+    session::KeyEventKind,
+};
+
+// ioctl request numbers from linux/input.h.
+const EVIOCGRAB: libc::c_ulong = 0x40044590;
+/// EVIOCGBIT(EV_KEY, KEY_BITS_LENGTH)
+const EVIOCGBIT_KEY: libc::c_ulong = 0x80604521;
+const KEY_BITS_LENGTH: usize = 96;
+
+/// The state shared between the tracer and its reader threads for as long as it is running.
+struct Session {
+    running: AtomicBool,
+    subscribers: Subscribers,
+    emitter: Mutex<Option<Box<dyn KeyEventEmitter>>>,
+}
+
+/// Reads raw `input_event` structures from an evdev device until it runs out of data or the session is stopped.
+///
+/// Presses and releases are dispatched to the subscribers, but repeats aren't, since they don't add anything to the statistics.
+///
+/// This works on anything that produces the same byte stream as `/dev/input/event*`, which makes it possible to feed it from a pipe.
+/// If the device is grabbed, nobody else sees its events, so we pass on everything that the callbacks didn't suppress.
+fn read_events<Device: Read>(
+    mut device: Device,
+    grabbed: bool,
+    session: &Session,
+) -> io::Result<()> {
+    static WARNED_ABOUT_REMAPPING: AtomicBool = AtomicBool::new(false);
+    let mut buffer = [0u8; size_of::<input_event>()];
+    // Keys whose press was suppressed. Their repeats and releases have to be suppressed too.
+    let mut suppressed_scancodes = BTreeSet::new();
+    let mut held_keys = BTreeSet::new();
+    loop {
+        match device.read_exact(&mut buffer) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        if !session.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let event = unsafe { (buffer.as_ptr() as *const input_event).read_unaligned() };
+        if event.type_ == EV_KEY {
+            if let Some(key_code) = key_code_from_scancode(event.code) {
+                if event.value == KEY_PRESSED {
+                    held_keys.insert(key_code);
+                } else if event.value == KEY_RELEASED {
+                    held_keys.remove(&key_code);
+                }
+            }
+            if event.value == KEY_PRESSED {
+                if let Some(key_code) = key_code_from_scancode(event.code) {
+                    let context =
+                        session
+                            .subscribers
+                            .dispatch(key_code, KeyEventKind::Down, &held_keys);
+                    if !grabbed {
+                        if (context.is_suppressed() || !context.sent_keystrokes().is_empty())
+                            && !WARNED_ABOUT_REMAPPING.swap(true, Ordering::SeqCst)
+                        {
+                            eprintln!("Can't remap keystrokes from a device we haven't grabbed");
+                        }
+                        continue;
+                    }
+                    let mut emitter = session.emitter.lock().unwrap();
+                    if let Some(emitter) = &mut *emitter {
+                        for (key_code, kind) in context.key_events() {
+                            emit_key_event(emitter.as_mut(), key_code, kind)?;
+                        }
+                    }
+                    if context.is_suppressed() {
+                        suppressed_scancodes.insert(event.code);
+                        continue;
+                    }
+                }
+            } else {
+                if event.value == KEY_RELEASED {
+                    if let Some(key_code) = key_code_from_scancode(event.code) {
+                        session
+                            .subscribers
+                            .dispatch(key_code, KeyEventKind::Up, &held_keys);
+                    }
+                }
+                if suppressed_scancodes.contains(&event.code) {
+                    if event.value == KEY_RELEASED {
+                        suppressed_scancodes.remove(&event.code);
+                    }
+                    continue;
+                }
+            }
+        }
+        if grabbed {
+            if let Some(emitter) = &mut *session.emitter.lock().unwrap() {
+                emitter.emit(event.type_, event.code, event.value)?;
+            }
+        }
+    }
+}
+
+/// Checks whether the device has letter keys, so that we don't grab mice and the like.
+fn is_keyboard(device: &File) -> bool {
+    let mut key_bits = [0u8; KEY_BITS_LENGTH];
+    let result = unsafe {
+        libc::ioctl(
+            device.as_raw_fd(),
+            EVIOCGBIT_KEY as _,
+            key_bits.as_mut_ptr(),
+        )
+    };
+    result >= 0 && key_bits[KEY_A as usize / 8] & (1 << (KEY_A % 8)) != 0
+}
+
+fn set_grabbed(device: &File, grabbed: bool) -> io::Result<()> {
+    check_ioctl(unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB as _, grabbed as libc::c_int) })
+}
+
+fn is_event_device(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("event"))
+}
+
+/// Waits until the process is ended. The tracer's threads do all of the work.
+pub fn run_event_loop() {
+    loop {
+        thread::park();
+    }
+}
+
+/// Reads key presses from every `/dev/input/event*` device we have access to.
+///
+/// If we can create a virtual keyboard through uinput, the physical keyboards are grabbed so that keystrokes can be suppressed and remapped.
+#[derive(Default)]
+pub struct Tracer {
+    subscribers: Subscribers,
+    session: Option<Arc<Session>>,
+    grabbed_devices: Vec<File>,
+}
+
+impl KeyEventSource for Tracer {
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.session.is_some() {
+            return Ok(());
+        }
+        let mut devices = Vec::new();
+        for entry in fs::read_dir("/dev/input")?.flatten() {
+            let path = entry.path();
+            if !is_event_device(&path) {
+                continue;
+            }
+            // Devices we aren't allowed to read (usually because we aren't in the `input` group) are skipped.
+            if let Ok(device) = File::open(&path) {
+                devices.push((path, device));
+            }
+        }
+        // The virtual keyboard is created after listing the devices so that we don't end up reading our own output.
+        let emitter: Option<Box<dyn KeyEventEmitter>> = match VirtualKeyboard::create() {
+            Ok(virtual_keyboard) => Some(Box::new(virtual_keyboard)),
+            Err(error) => {
+                eprintln!(
+                    "Couldn't create a virtual keyboard, keystrokes won't be remapped: {error}"
+                );
+                None
+            }
+        };
+        let can_remap = emitter.is_some();
+        if can_remap {
+            // Grabbing a keyboard while a key is held down (like the enter key that started us) leaves that key stuck.
+            thread::sleep(Duration::from_millis(250));
+        }
+        let session = Arc::new(Session {
+            running: AtomicBool::new(true),
+            subscribers: self.subscribers.clone(),
+            emitter: Mutex::new(emitter),
+        });
+        for (path, device) in devices {
+            let grabbed = can_remap && is_keyboard(&device) && set_grabbed(&device, true).is_ok();
+            if grabbed {
+                self.grabbed_devices.push(device.try_clone()?);
+            }
+            let session = session.clone();
+            thread::spawn(move || {
+                if let Err(error) = read_events(device, grabbed, &session) {
+                    eprintln!("Stopped reading {}: {error}", path.display());
+                }
+            });
+        }
+        self.session = Some(session);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        // The reader threads are blocked waiting for events, so they only notice this once the next one arrives.
+        if let Some(session) = self.session.take() {
+            session.running.store(false, Ordering::SeqCst);
+            for device in self.grabbed_devices.drain(..) {
+                let _ = set_grabbed(&device, false);
+            }
+            *session.emitter.lock().unwrap() = None;
+        }
+    }
+
+    fn subscribe(&mut self, callback: KeyEventCallback) -> SubscriptionId {
+        self.subscribers.subscribe(callback)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscribers.unsubscribe(id);
+    }
+
+    fn inject(&mut self, key_code: KeyCode) -> Result<(), Box<dyn Error>> {
+        let session = self.session.as_ref().ok_or("The tracer isn't running")?;
+        let mut emitter = session.emitter.lock().unwrap();
+        let emitter = emitter
+            .as_mut()
+            .ok_or("There is no virtual keyboard to send keystrokes with")?;
+        emit_key_stroke(emitter.as_mut(), key_code)?;
+        Ok(())
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Cursor, Write},
+        os::fd::FromRawFd,
+        slice,
+    };
+
+    use libc::timeval;
+
+    use super::*;
+    use crate::{
+        input::RecordingEmitter,
+        keyboard::Keystroke,
+        scancode::{EV_SYN, KEY_S, SYN_REPORT},
+    };
+
+    const EV_MSC: u16 = 0x04;
+    const MSC_SCAN: u16 = 0x04;
+    const KEY_REPEATED: i32 = 2;
+    /// KEY_F1, which has no `KeyCode`.
+    const UNKNOWN_SCANCODE: u16 = 59;
+
+    fn event_bytes(events: &[(u16, u16, i32)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for &(type_, code, value) in events {
+            let event = input_event {
+                time: timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_,
+                code,
+                value,
+            };
+            bytes.extend_from_slice(unsafe {
+                slice::from_raw_parts(
+                    &event as *const input_event as *const u8,
+                    size_of::<input_event>(),
+                )
+            });
+        }
+        bytes
+    }
+
+    type Dispatched = Arc<Mutex<Vec<(KeyCode, KeyEventKind)>>>;
+
+    /// A session that isn't grabbing anything, along with the key events its subscriber has seen.
+    fn recording_session() -> (Session, Dispatched) {
+        let subscribers = Subscribers::default();
+        let dispatched = Arc::new(Mutex::new(Vec::new()));
+        let dispatched_clone = dispatched.clone();
+        subscribers.subscribe(Box::new(move |context, key_code| {
+            dispatched_clone
+                .lock()
+                .unwrap()
+                .push((key_code, context.kind()));
+        }));
+        let session = Session {
+            running: AtomicBool::new(true),
+            subscribers,
+            emitter: Mutex::new(None),
+        };
+        (session, dispatched)
+    }
+
+    /// What `typing` dispatches.
+    const TYPED: [(KeyCode, KeyEventKind); 3] = [
+        (KeyCode::A, KeyEventKind::Down),
+        (KeyCode::A, KeyEventKind::Up),
+        (KeyCode::S, KeyEventKind::Down),
+    ];
+
+    /// A press of A with everything else a keyboard sends around it, then a press of a key we don't know and one of S.
+    fn typing() -> Vec<u8> {
+        event_bytes(&[
+            (EV_MSC, MSC_SCAN, 30),
+            (EV_KEY, KEY_A, KEY_PRESSED),
+            (EV_SYN, SYN_REPORT, 0),
+            (EV_KEY, KEY_A, KEY_REPEATED),
+            (EV_SYN, SYN_REPORT, 0),
+            (EV_KEY, KEY_A, KEY_RELEASED),
+            (EV_SYN, SYN_REPORT, 0),
+            (EV_KEY, UNKNOWN_SCANCODE, KEY_PRESSED),
+            (EV_KEY, UNKNOWN_SCANCODE, KEY_RELEASED),
+            (EV_KEY, KEY_S, KEY_PRESSED),
+            (EV_SYN, SYN_REPORT, 0),
+        ])
+    }
+
+    #[test]
+    fn only_presses_and_releases_of_known_keys_are_dispatched() {
+        let (session, dispatched) = recording_session();
+        read_events(Cursor::new(typing()), false, &session).unwrap();
+        assert_eq!(*dispatched.lock().unwrap(), TYPED);
+    }
+
+    #[test]
+    fn reads_from_a_pipe() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (reader, mut writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let writer_thread = thread::spawn(move || {
+            // Written a byte at a time, so that events arrive in pieces like they can from a real pipe.
+            for byte in typing() {
+                writer.write_all(&[byte]).unwrap();
+            }
+        });
+        let (session, dispatched) = recording_session();
+        read_events(reader, false, &session).unwrap();
+        writer_thread.join().unwrap();
+        assert_eq!(*dispatched.lock().unwrap(), TYPED);
+    }
+
+    #[test]
+    fn a_partial_event_ends_the_stream() {
+        let (session, dispatched) = recording_session();
+        let mut bytes = event_bytes(&[(EV_KEY, KEY_A, KEY_PRESSED)]);
+        bytes.extend_from_slice(&event_bytes(&[(EV_KEY, KEY_S, KEY_PRESSED)])[..4]);
+        read_events(Cursor::new(bytes), false, &session).unwrap();
+        assert_eq!(
+            *dispatched.lock().unwrap(),
+            [(KeyCode::A, KeyEventKind::Down)]
+        );
+    }
+
+    #[test]
+    fn grabbed_devices_are_remapped_and_passed_on() {
+        let subscribers = Subscribers::default();
+        subscribers.subscribe(Box::new(|context, key_code| {
+            if key_code == KeyCode::A && context.kind() == KeyEventKind::Down {
+                context.suppress();
+                context.send_keystroke(Keystroke::new(KeyCode::S));
+            }
+        }));
+        let emitter = RecordingEmitter::default();
+        let session = Session {
+            running: AtomicBool::new(true),
+            subscribers,
+            emitter: Mutex::new(Some(Box::new(emitter.clone()))),
+        };
+        read_events(Cursor::new(typing()), true, &session).unwrap();
+        assert_eq!(
+            emitter.events(),
+            vec![
+                (EV_MSC, MSC_SCAN, 30),
+                // A is suppressed, along with its repeat and release, and S is typed instead.
+                (EV_KEY, KEY_S, KEY_PRESSED),
+                (EV_SYN, SYN_REPORT, 0),
+                (EV_KEY, KEY_S, KEY_RELEASED),
+                (EV_SYN, SYN_REPORT, 0),
+                (EV_SYN, SYN_REPORT, 0),
+                (EV_SYN, SYN_REPORT, 0),
+                (EV_SYN, SYN_REPORT, 0),
+                (EV_KEY, UNKNOWN_SCANCODE, KEY_PRESSED),
+                (EV_KEY, UNKNOWN_SCANCODE, KEY_RELEASED),
+                (EV_KEY, KEY_S, KEY_PRESSED),
+                (EV_SYN, SYN_REPORT, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_is_dispatched_once_stopped() {
+        let (session, dispatched) = recording_session();
+        session.running.store(false, Ordering::SeqCst);
+        read_events(Cursor::new(typing()), false, &session).unwrap();
+        assert!(dispatched.lock().unwrap().is_empty());
+    }
+}
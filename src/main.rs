@@ -1,51 +1,123 @@
 use std::{
+    env,
     error::Error,
+    fs,
+    path::Path,
+    process,
     sync::{Arc, Mutex},
     time::Instant,
 };
 
-use digram_timing::DigramTimingHint;
-use gui::launch_gui;
+use keyboard_layout_optimizer::{
+    digram_timing::DigramTimingHint,
+    geometry::KeyboardGeometry,
+    gui::launch_gui,
+    key_event_source::KeyEventSource,
+    keyboard::KeyboardLayout,
+    kle::KleKeyboard,
+    layers::LayeredLayout,
+    layout_creator::{LayoutCreator, LayoutHint},
+    remapper::remap_key_press,
+    session::{KeyEventKind, SessionRecorder, SessionReplayer},
+    trace::Tracer,
+};
 
-use keyboard::KeyboardLayout;
-use layout_creator::LayoutCreator;
-use trace::Tracer;
+mod cli;
 
-use crate::layout_creator::LayoutHint;
+/// Every keystroke is appended to this file, so that no typing data is lost.
+const SESSION_LOG_PATH: &str = "keystrokes.log";
+/// The digram timing statistics are saved here on exit, which is much quicker to load than replaying the whole session log.
+/// Anything logged after they were saved (say, if the program was killed) is replayed on top of them.
+const STATISTICS_PATH: &str = "digram_timing.stats";
+/// If there is a keyboard-layout-editor.com export here, the GUI draws the keyboard from it instead of a standard ANSI board.
+const KEYBOARD_PATH: &str = "keyboard.json";
+/// Where the GUI saves layouts and loads them from, unless another path is typed in.
+const LAYOUT_PATH: &str = "layout.txt";
 
-mod digram_timing;
-mod gui;
-mod keyboard;
-mod layout_creator;
+fn load_digram_timing_hint() -> Result<DigramTimingHint, Box<dyn Error>> {
+    let mut hint = if Path::new(STATISTICS_PATH).exists() {
+        let hint = DigramTimingHint::load(STATISTICS_PATH)?;
+        // Statistics from before the log length was saved were only ever written on exit, so they already have the whole log.
+        if hint.log_length().is_none() {
+            return Ok(hint);
+        }
+        hint
+    } else {
+        DigramTimingHint::default()
+    };
+    if Path::new(SESSION_LOG_PATH).exists() {
+        let replayer =
+            SessionReplayer::open_from(SESSION_LOG_PATH, hint.log_length().unwrap_or(0))?;
+        hint.replay(&replayer);
+        hint.set_log_length(Some(replayer.length()));
+    }
+    Ok(hint)
+}
 
-#[cfg_attr(windows, path = "windows/input.rs")]
-mod input;
-#[cfg_attr(windows, path = "windows/trace.rs")]
-mod trace;
+/// Saves the statistics along with how much of the session log they include.
+fn save_digram_timing_hint(
+    layout_creator: &Mutex<LayoutCreator>,
+    hint: &Mutex<DigramTimingHint>,
+) -> Result<(), Box<dyn Error>> {
+    // Key presses are fed in and logged while the layout creator is locked, so nothing can be logged in between.
+    let _layout_creator = layout_creator.lock().unwrap();
+    let mut hint = hint.lock().unwrap();
+    hint.set_log_length(Some(fs::metadata(SESSION_LOG_PATH)?.len()));
+    hint.save(STATISTICS_PATH)?;
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let mut arguments = env::args().skip(1).peekable();
+    if arguments.peek().is_some() {
+        if let Err(error) = cli::run(arguments) {
+            eprintln!("{error}");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let geometry = if Path::new(KEYBOARD_PATH).exists() {
+        let keyboard = KleKeyboard::load(KEYBOARD_PATH)?;
+        let qwerty = KeyboardLayout::qwerty_main_block();
+        cli::report_extra_keys(&keyboard, &qwerty);
+        keyboard.geometry_for(&qwerty)
+    } else {
+        KeyboardGeometry::ansi()
+    };
+    let digram_timing_hint = Arc::new(Mutex::new(load_digram_timing_hint()?));
     let layout_creator = Arc::new(Mutex::new(LayoutCreator::new(vec![Box::new(
-        DigramTimingHint::default(),
+        digram_timing_hint.clone(),
     )])));
-    let active_keyboard_layout: Arc<Mutex<Option<KeyboardLayout>>> = Arc::new(Mutex::new(None));
+    let mut session_recorder = SessionRecorder::open(SESSION_LOG_PATH)?;
+    let active_keyboard_layout: Arc<Mutex<Option<LayeredLayout>>> = Arc::new(Mutex::new(None));
     let layout_creator2 = layout_creator.clone();
+    let layout_creator3 = layout_creator.clone();
     let active_keyboard_layout2 = active_keyboard_layout.clone();
     let active_keyboard_layout3 = active_keyboard_layout.clone();
-    let _tracer = Tracer::new(move |context, key_code| {
+    let mut tracer = Tracer::default();
+    tracer.subscribe(Box::new(move |context, key_code| {
         let mut layout_creator = layout_creator2.lock().unwrap();
         let active_keyboard_layout = active_keyboard_layout3.lock().unwrap();
-        layout_creator.receive_key_press(key_code, Instant::now());
-        if let Some(active_keyboard_layout) = *active_keyboard_layout {
-            context.suppress();
-            let translated_keystroke = active_keyboard_layout
-                .key_at(KeyboardLayout::QWERTY.position_of(key_code).unwrap());
-            context.send_keystroke(translated_keystroke);
+        let time = Instant::now();
+        if context.kind() == KeyEventKind::Down {
+            layout_creator.receive_key_press(key_code, time);
         }
-    });
-    launch_gui(
-        Box::new(move || {
-            let layout_creator = layout_creator.lock().unwrap();
-            layout_creator.create_layout()
+        if let Err(error) = session_recorder
+            .record(key_code, context.kind(), time)
+            .and_then(|()| session_recorder.flush())
+        {
+            eprintln!("Failed to record keystroke: {error}");
+        }
+        if let Some(active_keyboard_layout) = &*active_keyboard_layout {
+            remap_key_press(context, key_code, active_keyboard_layout);
+        }
+    }));
+    tracer.start()?;
+    let result = launch_gui(
+        Box::new(move |template| {
+            let mut layout_creator = layout_creator.lock().unwrap();
+            layout_creator.set_template(template.clone());
+            LayeredLayout::new(layout_creator.create_layout())
         }),
         Box::new(move |layout| {
             let mut active_keyboard_layout = active_keyboard_layout.lock().unwrap();
@@ -55,5 +127,9 @@ fn main() -> Result<(), Box<dyn Error>> {
             let mut active_keyboard_layout = active_keyboard_layout2.lock().unwrap();
             *active_keyboard_layout = None;
         }),
-    )
+        geometry,
+        LAYOUT_PATH.to_string(),
+    );
+    save_digram_timing_hint(&layout_creator3, &digram_timing_hint)?;
+    result
 }
@@ -0,0 +1,132 @@
+//! The letter blocks of well-known layouts, to start optimizing from or to compare against.
+//!
+//! Each preset is written as the characters its three rows type on US QWERTY, so a character stands for the key which types it
+//! there. Layouts made for other systems (QWERTZ and AZERTY) are written as where they put the letters; punctuation that has no
+//! key of its own on US QWERTY is left where QWERTY has it.
+
+use crate::keyboard::{KeyCode, KeyboardLayout};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub rows: [&'static str; 3],
+}
+
+impl Preset {
+    pub fn layout(&self) -> KeyboardLayout {
+        let rows: Vec<Vec<KeyCode>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.chars()
+                    .map(|character| KeyCode::from_character(character).unwrap())
+                    .collect()
+            })
+            .collect();
+        let rows: Vec<&[KeyCode]> = rows.iter().map(Vec::as_slice).collect();
+        KeyboardLayout::from_rows(&rows)
+    }
+}
+
+pub const QWERTY: Preset = Preset {
+    name: "QWERTY",
+    rows: ["qwertyuiop", "asdfghjkl;", "zxcvbnm,./"],
+};
+
+pub const DVORAK: Preset = Preset {
+    name: "Dvorak",
+    rows: ["',.pyfgcrl", "aoeuidhtns", ";qjkxbmwvz"],
+};
+
+pub const COLEMAK: Preset = Preset {
+    name: "Colemak",
+    rows: ["qwfpgjluy;", "arstdhneio", "zxcvbkm,./"],
+};
+
+pub const COLEMAK_DH: Preset = Preset {
+    name: "Colemak-DH",
+    rows: ["qwfpbjluy;", "arstgmneio", "zxcdvkh,./"],
+};
+
+pub const WORKMAN: Preset = Preset {
+    name: "Workman",
+    rows: ["qdrwbjfup;", "ashtgyneoi", "zxmcvkl,./"],
+};
+
+pub const HALMAK: Preset = Preset {
+    name: "Halmak",
+    rows: ["wlrbz;qudj", "shnt,.aeoi", "fmvc/gpxky"],
+};
+
+pub const CANARY: Preset = Preset {
+    name: "Canary",
+    rows: ["wlypkzxou;", "crstbfneia", "jvdgqmh/,."],
+};
+
+/// Graphite's `;` and `/` are outside the letter block, on the `'` and `-` keys that it moves into it.
+pub const GRAPHITE: Preset = Preset {
+    name: "Graphite",
+    rows: ["bldwz'fouj", "nrtsgyhaei", "qxmcvkp,.-"],
+};
+
+pub const QWERTZ: Preset = Preset {
+    name: "QWERTZ",
+    rows: ["qwertzuiop", "asdfghjkl;", "yxcvbnm,./"],
+};
+
+/// AZERTY's `;` goes on QWERTY's `,` key and its `,` on the `m` key. Its `:` and `!` stay on the `.` and `/` keys.
+pub const AZERTY: Preset = Preset {
+    name: "AZERTY",
+    rows: ["azertyuiop", "qsdfghjklm", "wxcvbn,;./"],
+};
+
+pub const PRESETS: [Preset; 10] = [
+    QWERTY, DVORAK, COLEMAK, COLEMAK_DH, WORKMAN, HALMAK, CANARY, GRAPHITE, QWERTZ, AZERTY,
+];
+
+/// Finds a preset by its name, ignoring case.
+pub fn preset(name: &str) -> Option<Preset> {
+    PRESETS
+        .into_iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn presets_have_every_letter_and_no_key_twice() {
+        let expected: BTreeSet<KeyCode> = "abcdefghijklmnopqrstuvwxyz,."
+            .chars()
+            .map(|character| KeyCode::from_character(character).unwrap())
+            .collect();
+        for preset in PRESETS {
+            assert!(
+                preset.rows.iter().all(|row| row.chars().count() == 10),
+                "{}",
+                preset.name
+            );
+            let keys: Vec<KeyCode> = preset
+                .rows
+                .concat()
+                .chars()
+                .map(|character| KeyCode::from_character(character).unwrap())
+                .collect();
+            let distinct: BTreeSet<KeyCode> = keys.iter().copied().collect();
+            assert_eq!(distinct.len(), 30, "{}", preset.name);
+            assert!(distinct.is_superset(&expected), "{}", preset.name);
+        }
+    }
+
+    #[test]
+    fn presets_with_only_qwertys_keys_are_rearrangements_of_it() {
+        let qwerty: BTreeSet<char> = QWERTY.rows.concat().chars().collect();
+        for preset in [COLEMAK, COLEMAK_DH, WORKMAN, HALMAK, CANARY, QWERTZ, AZERTY] {
+            let keys: BTreeSet<char> = preset.rows.concat().chars().collect();
+            assert_eq!(keys, qwerty, "{}", preset.name);
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! Treats creating a layout as a quadratic assignment problem, so that pairs of keys are taken into account.
+//!
+//! `LayoutHint`s can only say how well a key suits a position on its own. Here, every pair of keys has a flow (how often the
+//! digram is typed) and every pair of positions has a cost (how long it takes to go from one to the other). The cost of a layout
+//! is the sum of flow × cost over all digrams, which is the average time per digram when the flows add up to 1.
+//!
+//! Quadratic assignment can't be solved exactly at this size, so the layout is improved by swapping keys, either with a plain
+//! local search or with a tabu search that can get out of local minima.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    digram_timing::DigramTimingHint,
+    evaluator::KeyStatistics,
+    keyboard::{KeyCode, KeyboardLayout},
+    random::Random,
+};
+
+pub struct QuadraticAssignment {
+    /// How often each digram is typed, adding up to 1.
+    key_pair_flows: BTreeMap<(KeyCode, KeyCode), f64>,
+    positions: Vec<(usize, usize)>,
+    /// The cost of typing the position with the second index straight after the one with the first. The indices are into
+    /// `positions`.
+    position_pair_costs: Vec<Vec<f64>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuadraticAssignmentResult {
+    pub layout: KeyboardLayout,
+    pub cost: f64,
+}
+
+impl QuadraticAssignment {
+    pub fn new(
+        key_pair_flows: &BTreeMap<(KeyCode, KeyCode), f64>,
+        positions: Vec<(usize, usize)>,
+        position_pair_costs: Vec<Vec<f64>>,
+    ) -> Self {
+        let total: f64 = key_pair_flows.values().sum();
+        Self {
+            key_pair_flows: key_pair_flows
+                .iter()
+                .filter(|(_, &flow)| flow > 0.0)
+                .map(|(&key_pair, &flow)| (key_pair, flow / total))
+                .collect(),
+            positions,
+            position_pair_costs,
+        }
+    }
+
+    /// Uses the digrams in `statistics` as the flows, and the typing speed recorded by `timing_hint` between `positions` as the
+    /// costs (in seconds).
+    ///
+    /// Position pairs without any timing data get the average of the ones that have it.
+    pub fn from_timing(
+        statistics: &KeyStatistics,
+        timing_hint: &DigramTimingHint,
+        positions: &[(usize, usize)],
+    ) -> Self {
+        let costs: Vec<Vec<_>> = positions
+            .iter()
+            .map(|&from| {
+                positions
+                    .iter()
+                    .map(|&to| {
+                        timing_hint
+                            .estimated_time_between(from, to)
+                            .map(|time| time.as_secs_f64())
+                    })
+                    .collect()
+            })
+            .collect();
+        let known_costs: Vec<f64> = costs.iter().flatten().flatten().copied().collect();
+        let fallback = if known_costs.is_empty() {
+            1.0
+        } else {
+            known_costs.iter().sum::<f64>() / known_costs.len() as f64
+        };
+        let costs = costs
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|cost| cost.unwrap_or(fallback))
+                    .collect()
+            })
+            .collect();
+        Self::new(&statistics.bigrams, positions.to_vec(), costs)
+    }
+
+    /// The cost of typing `second` straight after `first`. Positions that aren't part of the problem cost nothing.
+    fn position_pair_cost(&self, first: (usize, usize), second: (usize, usize)) -> f64 {
+        let index_of = |position| self.positions.iter().position(|&other| other == position);
+        match (index_of(first), index_of(second)) {
+            (Some(first), Some(second)) => self.position_pair_costs[first][second],
+            _ => 0.0,
+        }
+    }
+
+    pub fn cost(&self, layout: &KeyboardLayout) -> f64 {
+        self.key_pair_flows
+            .iter()
+            .filter_map(|(&(first, second), &flow)| {
+                let first = layout.position_of(first)?;
+                let second = layout.position_of(second)?;
+                Some(flow * self.position_pair_cost(first, second))
+            })
+            .sum()
+    }
+
+    /// Keeps making the swap which lowers the cost the most, until no swap lowers it.
+    pub fn local_search(&self, start: KeyboardLayout) -> QuadraticAssignmentResult {
+        let mut state = SearchState::new(self, &start);
+        while let Some((delta, first, second)) = state.best_swap(|_, _, _| true) {
+            if delta >= 0.0 {
+                break;
+            }
+            state.swap(first, second);
+        }
+        state.result(&start, self)
+    }
+}
+
+/// The key positions as plain indices, so that swaps can be evaluated quickly.
+struct SearchState {
+    /// The positions of the starting layout, which the keys are moved around.
+    layout_positions: Vec<(usize, usize)>,
+    /// The flows between the keys of the starting layout, which are indexed by their position in it.
+    flows: Vec<Vec<f64>>,
+    /// The costs between the positions of the starting layout.
+    costs: Vec<Vec<f64>>,
+    /// The position of each key.
+    positions: Vec<usize>,
+}
+
+impl SearchState {
+    fn new(problem: &QuadraticAssignment, start: &KeyboardLayout) -> Self {
+        let layout_positions: Vec<_> = start.positions().collect();
+        let keys: Vec<KeyCode> = start.iter().collect();
+        let flows = keys
+            .iter()
+            .map(|&first| {
+                keys.iter()
+                    .map(|&second| {
+                        problem
+                            .key_pair_flows
+                            .get(&(first, second))
+                            .copied()
+                            .unwrap_or(0.0)
+                    })
+                    .collect()
+            })
+            .collect();
+        let costs = layout_positions
+            .iter()
+            .map(|&first| {
+                layout_positions
+                    .iter()
+                    .map(|&second| problem.position_pair_cost(first, second))
+                    .collect()
+            })
+            .collect();
+        Self {
+            positions: (0..keys.len()).collect(),
+            layout_positions,
+            flows,
+            costs,
+        }
+    }
+
+    /// The cost of all the digrams which involve either of the two keys.
+    fn cost_involving(&self, first: usize, second: usize) -> f64 {
+        let mut cost = 0.0;
+        for key in [first, second] {
+            for other in 0..self.positions.len() {
+                cost +=
+                    self.flows[key][other] * self.costs[self.positions[key]][self.positions[other]];
+                cost +=
+                    self.flows[other][key] * self.costs[self.positions[other]][self.positions[key]];
+            }
+        }
+        // The digrams between the two keys (and of each key with itself) were counted twice.
+        for (from, to) in [
+            (first, first),
+            (second, second),
+            (first, second),
+            (second, first),
+        ] {
+            cost -= self.flows[from][to] * self.costs[self.positions[from]][self.positions[to]];
+        }
+        cost
+    }
+
+    /// How much swapping the two keys would change the total cost.
+    fn swap_delta(&mut self, first: usize, second: usize) -> f64 {
+        let before = self.cost_involving(first, second);
+        self.positions.swap(first, second);
+        let after = self.cost_involving(first, second);
+        self.positions.swap(first, second);
+        after - before
+    }
+
+    fn swap(&mut self, first: usize, second: usize) {
+        self.positions.swap(first, second);
+    }
+
+    /// The allowed swap which lowers the cost the most (or raises it the least).
+    fn best_swap(
+        &mut self,
+        allowed: impl Fn(usize, usize, f64) -> bool,
+    ) -> Option<(f64, usize, usize)> {
+        let mut best = None;
+        for first in 0..self.positions.len() {
+            for second in first + 1..self.positions.len() {
+                let delta = self.swap_delta(first, second);
+                if best.is_none_or(|(best_delta, _, _)| delta < best_delta)
+                    && allowed(first, second, delta)
+                {
+                    best = Some((delta, first, second));
+                }
+            }
+        }
+        best
+    }
+
+    fn layout(&self, start: &KeyboardLayout) -> KeyboardLayout {
+        let mut layout = KeyboardLayout::new(BTreeMap::new());
+        for (key, key_code) in start.iter().enumerate() {
+            let (row, column) = self.layout_positions[self.positions[key]];
+            layout.set_key_at(row, column, key_code);
+        }
+        layout
+    }
+
+    fn result(
+        &self,
+        start: &KeyboardLayout,
+        problem: &QuadraticAssignment,
+    ) -> QuadraticAssignmentResult {
+        let layout = self.layout(start);
+        QuadraticAssignmentResult {
+            cost: problem.cost(&layout),
+            layout,
+        }
+    }
+}
+
+/// Swaps the best pair of keys every iteration, even if that makes the layout worse, but doesn't move a key back to where it
+/// recently was. This stops it from going straight back into the local minimum it just left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TabuSearch {
+    pub iterations: usize,
+    /// How many iterations a key is kept away from a position it has left. A random amount of up to half of this is added each time.
+    pub tabu_tenure: usize,
+    pub seed: u64,
+}
+
+impl Default for TabuSearch {
+    fn default() -> Self {
+        Self {
+            iterations: 2000,
+            tabu_tenure: 20,
+            seed: 0,
+        }
+    }
+}
+
+impl TabuSearch {
+    pub fn new(iterations: usize, tabu_tenure: usize, seed: u64) -> Self {
+        Self {
+            iterations,
+            tabu_tenure,
+            seed,
+        }
+    }
+
+    /// Looks for the layout with the lowest cost, starting from `start`. The result is the best layout that was seen.
+    pub fn optimize(
+        &self,
+        problem: &QuadraticAssignment,
+        start: KeyboardLayout,
+    ) -> QuadraticAssignmentResult {
+        let mut random = Random::new(self.seed);
+        let mut state = SearchState::new(problem, &start);
+        // The iteration until which each key isn't allowed back into each position.
+        let mut tabu_until = vec![vec![0; state.positions.len()]; state.positions.len()];
+        let mut current_cost = problem.cost(&start);
+        let mut best_cost = current_cost;
+        let mut best_positions = state.positions.clone();
+        for iteration in 0..self.iterations {
+            let positions = state.positions.clone();
+            // Either key going back to where it was is enough to make the swap tabu.
+            let is_tabu = |first: usize, second: usize| {
+                tabu_until[first][positions[second]] > iteration
+                    || tabu_until[second][positions[first]] > iteration
+            };
+            // A tabu swap is still allowed if it finds a new best layout.
+            let Some((delta, first, second)) = state.best_swap(|first, second, delta| {
+                !is_tabu(first, second) || current_cost + delta < best_cost
+            }) else {
+                break;
+            };
+            for key in [first, second] {
+                tabu_until[key][state.positions[key]] =
+                    iteration + self.tabu_tenure + random.below(self.tabu_tenure / 2 + 1);
+            }
+            state.swap(first, second);
+            current_cost += delta;
+            if current_cost < best_cost {
+                best_cost = current_cost;
+                best_positions = state.positions.clone();
+            }
+        }
+        state.positions = best_positions;
+        state.result(&start, problem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A problem with random flows between the first 12 letters and random costs between their positions on QWERTY.
+    fn random_problem(seed: u64) -> (QuadraticAssignment, KeyboardLayout) {
+        let mut random = Random::new(seed);
+        let keys = &KeyCode::ALL[..12];
+        let start = KeyboardLayout::from_rows(&[&keys[..10], &keys[10..]]);
+        let mut flows = BTreeMap::new();
+        for &first in keys {
+            for &second in keys {
+                if random.below(3) > 0 {
+                    flows.insert((first, second), random.fraction());
+                }
+            }
+        }
+        let positions: Vec<_> = start.positions().collect();
+        let costs = positions
+            .iter()
+            .map(|_| positions.iter().map(|_| random.fraction()).collect())
+            .collect();
+        (QuadraticAssignment::new(&flows, positions, costs), start)
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "{value} isn't close to {expected}"
+        );
+    }
+
+    #[test]
+    fn swap_deltas_match_the_change_in_cost() {
+        let (problem, start) = random_problem(1);
+        let mut state = SearchState::new(&problem, &start);
+        state.swap(0, 5);
+        let layout = state.layout(&start);
+        for first in 0..state.positions.len() {
+            for second in first + 1..state.positions.len() {
+                let delta = state.swap_delta(first, second);
+                state.swap(first, second);
+                let swapped = state.layout(&start);
+                state.swap(first, second);
+                assert_close(delta, problem.cost(&swapped) - problem.cost(&layout));
+            }
+        }
+        let (delta, first, second) = state.best_swap(|_, _, _| true).unwrap();
+        assert_close(delta, state.swap_delta(first, second));
+    }
+
+    #[test]
+    fn local_search_never_raises_the_cost() {
+        for seed in 0..5 {
+            let (problem, start) = random_problem(seed);
+            let result = problem.local_search(start.clone());
+            assert!(result.cost <= problem.cost(&start));
+            // It only stops when no swap helps.
+            let mut state = SearchState::new(&problem, &result.layout);
+            let (delta, _, _) = state.best_swap(|_, _, _| true).unwrap();
+            assert!(delta >= -1e-12);
+        }
+    }
+
+    #[test]
+    fn tabu_search_keeps_the_best_layout_it_saw() {
+        let (problem, start) = random_problem(2);
+        let mut previous_cost = problem.cost(&start);
+        // With the same seed, a longer search goes through everything a shorter one did.
+        for iterations in [0, 1, 5, 20, 100] {
+            let result = TabuSearch::new(iterations, 4, 7).optimize(&problem, start.clone());
+            assert!(result.cost <= previous_cost + 1e-12);
+            previous_cost = result.cost;
+        }
+        let local = problem.local_search(start.clone());
+        let tabu = TabuSearch::new(100, 4, 7).optimize(&problem, start);
+        assert!(tabu.cost <= local.cost + 1e-12);
+    }
+
+    #[test]
+    fn the_reported_cost_is_the_layouts_cost() {
+        let (problem, start) = random_problem(3);
+        let positions: Vec<_> = start.positions().collect();
+        for result in [
+            problem.local_search(start.clone()),
+            TabuSearch::new(50, 4, 0).optimize(&problem, start.clone()),
+        ] {
+            let mut cost = 0.0;
+            for (&(first, second), &flow) in &problem.key_pair_flows {
+                let index_of = |key_code| {
+                    let position = result.layout.position_of(key_code).unwrap();
+                    positions
+                        .iter()
+                        .position(|&other| other == position)
+                        .unwrap()
+                };
+                cost += flow * problem.position_pair_costs[index_of(first)][index_of(second)];
+            }
+            assert_close(result.cost, cost);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Turns key presses on the physical keyboard into the keys at the same positions in another layout.
+
+use crate::{
+    key_event_source::Context, keyboard::KeyCode, layers::LayeredLayout, session::KeyEventKind,
+};
+
+/// Replaces the key press with what the key at the same position types in `layout`, on whichever layer the held keys make
+/// active. Keys which only switch layers are suppressed, while modifiers and keys at positions which the layout doesn't have
+/// are left alone. Releases are left alone too, since only presses can be remapped.
+pub fn remap_key_press(context: &mut Context, key_code: KeyCode, layout: &LayeredLayout) {
+    if context.kind() == KeyEventKind::Up {
+        return;
+    }
+    if layout.is_layer_key(key_code) {
+        context.suppress();
+        return;
+    }
+    if key_code.is_modifier() {
+        return;
+    }
+    if let Some(keystroke) = layout.keystroke_at(key_code.position(), context.held_keys()) {
+        context.suppress();
+        context.send_keystroke(keystroke);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        key_event_source::{KeyEventSource, MemoryKeyEventSource},
+        keyboard::{KeyboardLayout, Keystroke},
+        layers::Layer,
+        presets::COLEMAK,
+    };
+
+    fn remapping_source(layout: LayeredLayout) -> MemoryKeyEventSource {
+        let mut source = MemoryKeyEventSource::default();
+        let layout = Arc::new(Mutex::new(layout));
+        source.subscribe(Box::new(move |context, key_code| {
+            remap_key_press(context, key_code, &layout.lock().unwrap());
+        }));
+        source.start().unwrap();
+        source
+    }
+
+    fn tap(source: &mut MemoryKeyEventSource, key_codes: &[KeyCode]) {
+        for &key_code in key_codes {
+            source.press(key_code);
+            source.release(key_code);
+        }
+    }
+
+    #[test]
+    fn keys_type_what_the_layout_has_at_their_position() {
+        let mut source = remapping_source(LayeredLayout::new(COLEMAK.layout()));
+        // "dvorak" typed on Colemak's positions, plus Space which the layout doesn't have.
+        tap(
+            &mut source,
+            &[
+                KeyCode::G,
+                KeyCode::V,
+                KeyCode::Semicolon,
+                KeyCode::S,
+                KeyCode::Space,
+            ],
+        );
+        let typed: Vec<KeyCode> = source
+            .output()
+            .iter()
+            .map(|keystroke| keystroke.key_code)
+            .collect();
+        assert_eq!(
+            typed,
+            [
+                KeyCode::D,
+                KeyCode::V,
+                KeyCode::O,
+                KeyCode::R,
+                KeyCode::Space
+            ]
+        );
+    }
+
+    #[test]
+    fn shift_types_the_shifted_key_at_the_position() {
+        let mut source = remapping_source(LayeredLayout::new(COLEMAK.layout()));
+        source.press(KeyCode::LeftShift);
+        tap(&mut source, &[KeyCode::E]);
+        source.release(KeyCode::LeftShift);
+        assert_eq!(source.output(), [Keystroke::shifted(KeyCode::F)]);
+    }
+
+    #[test]
+    fn layer_keys_switch_layers_without_typing() {
+        let mut layout = LayeredLayout::new(KeyboardLayout::qwerty());
+        let mut symbols = Layer::new("Symbols", vec![KeyCode::CapsLock]);
+        symbols.set_keystroke_at(KeyCode::A.position(), Keystroke::shifted(KeyCode::Digit9));
+        layout.add_layer(symbols);
+        let mut source = remapping_source(layout);
+
+        source.press(KeyCode::CapsLock);
+        tap(&mut source, &[KeyCode::A, KeyCode::S]);
+        source.release(KeyCode::CapsLock);
+        tap(&mut source, &[KeyCode::A]);
+
+        assert_eq!(
+            source.output(),
+            [
+                Keystroke::shifted(KeyCode::Digit9),
+                Keystroke::new(KeyCode::S),
+                Keystroke::new(KeyCode::A)
+            ]
+        );
+    }
+}
@@ -0,0 +1,488 @@
+//! Recording of keystroke sessions to disk, and replaying them later.
+//!
+//! The log starts with a short header, followed by a stream of records:
+//! - A session start record holds the wall clock time (in microseconds since the Unix epoch) that recording started at.
+//!   A new one is written every time the log is opened, so one file can hold many sessions.
+//! - A key record holds the key, whether it went down or up, and the number of microseconds since the previous record as a varint.
+//!   Since most keystrokes are well under a second apart, a key record is usually four bytes.
+//!
+//! If the recorder is killed halfway through writing a record, the log ends in part of one. Reading stops before it, and the
+//! recorder cuts it off before it adds anything else.
+
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    key_event_source::{KeyEventCallback, KeyEventSource, Subscribers, SubscriptionId},
+    keyboard::KeyCode,
+    layout_creator::LayoutHint,
+};
+
+const MAGIC: &[u8; 4] = b"KLOS";
+const VERSION: u8 = 1;
+
+const SESSION_START_TAG: u8 = 0;
+const KEY_DOWN_TAG: u8 = 1;
+const KEY_UP_TAG: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventKind {
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedKeyEvent {
+    pub key_code: KeyCode,
+    pub kind: KeyEventKind,
+    /// The time since the start of the first session in the log.
+    pub time: Duration,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn write_varint(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("Varint is too long"))
+}
+
+/// Reads a byte, or returns `None` at the end of the stream.
+fn read_tag(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut tag = [0];
+    match reader.read_exact(&mut tag) {
+        Ok(()) => Ok(Some(tag[0])),
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn read_header(reader: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a keystroke session log"));
+    }
+    let mut version = [0];
+    reader.read_exact(&mut version)?;
+    if version[0] != VERSION {
+        return Err(invalid_data("Unsupported keystroke session log version"));
+    }
+    Ok(())
+}
+
+/// Counts the bytes read through it, to know where the last complete record ends.
+struct CountingReader<R> {
+    reader: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buffer)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Appends keystrokes to a session log.
+pub struct SessionRecorder<W: Write> {
+    writer: W,
+    last_time: Instant,
+}
+
+impl SessionRecorder<BufWriter<File>> {
+    /// Opens the log at `path` for appending, creating it if it doesn't exist yet.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let length = file.metadata()?.len();
+        let is_new = length == 0;
+        if !is_new {
+            file.rewind()?;
+            let complete_length = SessionReplayer::read(BufReader::new(&file))?.length;
+            if complete_length < length {
+                // Anything after the last complete record would garble the records written after it.
+                file.set_len(complete_length)?;
+            }
+        }
+        Self::new(BufWriter::new(file), is_new)
+    }
+}
+
+impl<W: Write> SessionRecorder<W> {
+    /// Starts a new session in `writer`. The header should only be written if nothing has been written to it before.
+    pub fn new(mut writer: W, write_header: bool) -> io::Result<Self> {
+        if write_header {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[VERSION])?;
+        }
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writer.write_all(&[SESSION_START_TAG])?;
+        writer.write_all(&(since_epoch.as_micros() as u64).to_le_bytes())?;
+        Ok(Self {
+            writer,
+            last_time: Instant::now(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        key_code: KeyCode,
+        kind: KeyEventKind,
+        time: Instant,
+    ) -> io::Result<()> {
+        let tag = match kind {
+            KeyEventKind::Down => KEY_DOWN_TAG,
+            KeyEventKind::Up => KEY_UP_TAG,
+        };
+        let time_since_last = time.saturating_duration_since(self.last_time);
+        self.last_time = self.last_time.max(time);
+        self.writer.write_all(&[tag, key_code.index() as u8])?;
+        write_varint(&mut self.writer, time_since_last.as_micros() as u64)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Plays back a recorded session log.
+///
+/// As a `KeyEventSource`, it delivers all of the recorded key events to its subscribers as soon as it is started.
+#[derive(Default)]
+pub struct SessionReplayer {
+    events: Vec<RecordedKeyEvent>,
+    /// How many bytes of the log there are up to the end of the last complete record.
+    length: u64,
+    /// The wall clock time that the event times count from, as time since the Unix epoch.
+    start_time: Option<SystemTime>,
+    subscribers: Subscribers,
+}
+
+impl SessionReplayer {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Opens the log at `path`, skipping the records before `offset`, which should be a `length` the log had earlier.
+    ///
+    /// Times start from the first record after the offset. If the log is shorter than that, it isn't the same log any more,
+    /// so all of it is read.
+    pub fn open_from(path: impl AsRef<Path>, offset: u64) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        if offset > file.metadata()?.len() {
+            return Self::read(BufReader::new(file));
+        }
+        let mut reader = CountingReader {
+            reader: BufReader::new(&mut file),
+            count: 0,
+        };
+        read_header(&mut reader)?;
+        if offset <= reader.count {
+            return Self::read_records(reader, None);
+        }
+        drop(reader);
+        file.seek(SeekFrom::Start(offset))?;
+        let reader = CountingReader {
+            reader: BufReader::new(file),
+            count: offset,
+        };
+        Self::read_records(reader, Some(Duration::ZERO))
+    }
+
+    /// Reads a log up to its last complete record.
+    pub fn read(reader: impl Read) -> io::Result<Self> {
+        let mut reader = CountingReader { reader, count: 0 };
+        read_header(&mut reader)?;
+        Self::read_records(reader, None)
+    }
+
+    /// Reads the records after the header, or after a record part way through the log when the time is already known.
+    fn read_records(
+        mut reader: CountingReader<impl Read>,
+        mut current_time: Option<Duration>,
+    ) -> io::Result<Self> {
+        let mut events = Vec::new();
+        let mut length = reader.count;
+        let mut start_time = None;
+        let mut read_record = || -> io::Result<bool> {
+            let Some(tag) = read_tag(&mut reader)? else {
+                return Ok(false);
+            };
+            match tag {
+                SESSION_START_TAG => {
+                    let mut micros = [0; 8];
+                    reader.read_exact(&mut micros)?;
+                    let session_start = Duration::from_micros(u64::from_le_bytes(micros));
+                    // Reading from part way through, the events before the first session start belong to the one before it.
+                    let start_time = *start_time.get_or_insert(
+                        session_start.saturating_sub(current_time.unwrap_or_default()),
+                    );
+                    // Sessions are normally in order, but the clock may have been changed in between.
+                    current_time = Some(
+                        session_start
+                            .saturating_sub(start_time)
+                            .max(current_time.unwrap_or_default()),
+                    );
+                }
+                KEY_DOWN_TAG | KEY_UP_TAG => {
+                    let mut key_index = [0];
+                    reader.read_exact(&mut key_index)?;
+                    let key_code = KeyCode::from_index(key_index[0] as usize)
+                        .ok_or_else(|| invalid_data("Unknown key code"))?;
+                    let time_since_last = Duration::from_micros(read_varint(&mut reader)?);
+                    let time = current_time
+                        .as_mut()
+                        .ok_or_else(|| invalid_data("Key event before the start of a session"))?;
+                    *time += time_since_last;
+                    events.push(RecordedKeyEvent {
+                        key_code,
+                        kind: if tag == KEY_DOWN_TAG {
+                            KeyEventKind::Down
+                        } else {
+                            KeyEventKind::Up
+                        },
+                        time: *time,
+                    });
+                }
+                _ => return Err(invalid_data("Unknown record type")),
+            }
+            length = reader.count;
+            Ok(true)
+        };
+        loop {
+            match read_record() {
+                Ok(true) => {}
+                Ok(false) => break,
+                // Only the last record can be cut short, since nothing is written after it.
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(Self {
+            events,
+            length,
+            start_time: start_time.map(|start_time| UNIX_EPOCH + start_time),
+            subscribers: Subscribers::default(),
+        })
+    }
+
+    pub fn events(&self) -> &[RecordedKeyEvent] {
+        &self.events
+    }
+
+    /// How many bytes of the log were read, up to the end of the last complete record.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// The wall clock time that the event times count from, or `None` if the log (or the part of it that was read) doesn't
+    /// have any session start records.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_time
+    }
+
+    /// Feeds every recorded key press into the hint, keeping the time between them the same as when they were recorded. The
+    /// event times count from `start`.
+    pub fn replay_into(&self, hint: &mut dyn LayoutHint, start: Instant) {
+        for event in &self.events {
+            if event.kind == KeyEventKind::Down {
+                hint.receive_key_press(event.key_code, start + event.time);
+            }
+        }
+    }
+}
+
+impl KeyEventSource for SessionReplayer {
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut held_keys = BTreeSet::new();
+        for event in &self.events {
+            match event.kind {
+                KeyEventKind::Down => {
+                    held_keys.insert(event.key_code);
+                }
+                KeyEventKind::Up => {
+                    held_keys.remove(&event.key_code);
+                }
+            }
+            self.subscribers
+                .dispatch(event.key_code, event.kind, &held_keys);
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) {}
+
+    fn subscribe(&mut self, callback: KeyEventCallback) -> SubscriptionId {
+        self.subscribers.subscribe(callback)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscribers.unsubscribe(id);
+    }
+
+    fn inject(&mut self, _key_code: KeyCode) -> Result<(), Box<dyn Error>> {
+        // There is nothing to send a recording's keystrokes to.
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    /// A log with a press and release of A, 10 ms apart, followed by a press of S 20 ms later.
+    fn log() -> Vec<u8> {
+        let mut log = Vec::new();
+        let mut recorder = SessionRecorder::new(&mut log, true).unwrap();
+        let start = recorder.last_time;
+        for (key_code, kind, milliseconds) in [
+            (KeyCode::A, KeyEventKind::Down, 0),
+            (KeyCode::A, KeyEventKind::Up, 10),
+            (KeyCode::S, KeyEventKind::Down, 30),
+        ] {
+            recorder
+                .record(key_code, kind, start + Duration::from_millis(milliseconds))
+                .unwrap();
+        }
+        log
+    }
+
+    fn key_events(replayer: &SessionReplayer) -> Vec<(KeyCode, KeyEventKind, Duration)> {
+        replayer
+            .events()
+            .iter()
+            .map(|event| (event.key_code, event.kind, event.time))
+            .collect()
+    }
+
+    #[test]
+    fn presses_and_releases_are_read_back() {
+        let replayer = SessionReplayer::read(log().as_slice()).unwrap();
+        assert_eq!(
+            key_events(&replayer),
+            [
+                (KeyCode::A, KeyEventKind::Down, Duration::ZERO),
+                (KeyCode::A, KeyEventKind::Up, Duration::from_millis(10)),
+                (KeyCode::S, KeyEventKind::Down, Duration::from_millis(30)),
+            ]
+        );
+        assert_eq!(replayer.length, log().len() as u64);
+    }
+
+    #[test]
+    fn a_cut_off_record_ends_the_log() {
+        let log = log();
+        let complete = SessionReplayer::read(log.as_slice()).unwrap();
+        // The last record is a tag, a key and 20 ms as a three byte varint.
+        for cut in 1..5 {
+            let replayer = SessionReplayer::read(&log[..log.len() - cut]).unwrap();
+            assert_eq!(key_events(&replayer), key_events(&complete)[..2]);
+            assert_eq!(replayer.length, log.len() as u64 - 5);
+        }
+    }
+
+    #[test]
+    fn logs_can_be_read_from_an_earlier_length() {
+        let path = env::temp_dir().join(format!("session-test-offset-{}.log", process::id()));
+        let log = log();
+        // Everything but the last record, which is five bytes long.
+        let offset = log.len() as u64 - 5;
+        fs::write(&path, &log).unwrap();
+        let newer = SessionReplayer::open_from(&path, offset).unwrap();
+        let longer = SessionReplayer::open_from(&path, log.len() as u64 + 1).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            key_events(&newer),
+            [(KeyCode::S, KeyEventKind::Down, Duration::from_millis(20))]
+        );
+        assert_eq!(newer.length(), log.len() as u64);
+        // A log shorter than the offset has been replaced, so all of it is new.
+        assert_eq!(longer.events().len(), 3);
+    }
+
+    #[test]
+    fn event_times_count_from_the_start_time() {
+        let mut log = MAGIC.to_vec();
+        log.push(VERSION);
+        let session_start = |log: &mut Vec<u8>, seconds: u64| {
+            log.push(SESSION_START_TAG);
+            log.extend((seconds * 1_000_000).to_le_bytes());
+        };
+        session_start(&mut log, 1);
+        log.extend([KEY_DOWN_TAG, KeyCode::A.index() as u8, 0]);
+        let offset = log.len() as u64;
+        log.extend([KEY_DOWN_TAG, KeyCode::S.index() as u8]);
+        write_varint(&mut log, 10_000).unwrap();
+        session_start(&mut log, 5);
+        log.extend([KEY_DOWN_TAG, KeyCode::D.index() as u8, 0]);
+        let path = env::temp_dir().join(format!("session-test-start-{}.log", process::id()));
+        fs::write(&path, &log).unwrap();
+        let whole = SessionReplayer::open(&path).unwrap();
+        let newer = SessionReplayer::open_from(&path, offset).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        for replayer in [whole, newer] {
+            let start_time = replayer.start_time().unwrap();
+            let last = replayer.events().last().unwrap();
+            assert_eq!(start_time + last.time, UNIX_EPOCH + Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn the_recorder_cuts_off_a_partial_record() {
+        let path = env::temp_dir().join(format!("session-test-{}.log", process::id()));
+        let log = log();
+        fs::write(&path, &log[..log.len() - 1]).unwrap();
+        let mut recorder = SessionRecorder::open(&path).unwrap();
+        recorder
+            .record(KeyCode::D, KeyEventKind::Down, Instant::now())
+            .unwrap();
+        recorder.flush().unwrap();
+        drop(recorder);
+
+        let replayer = SessionReplayer::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let key_codes: Vec<_> = replayer
+            .events()
+            .iter()
+            .map(|event| event.key_code)
+            .collect();
+        assert_eq!(key_codes, [KeyCode::A, KeyCode::A, KeyCode::D]);
+    }
+}
@@ -0,0 +1,242 @@
+//! Statistics about how long one transition between two key positions takes.
+//!
+//! Besides the mean and variance, the times are sorted into buckets which are 5% wider than the one before, so that the median
+//! and other percentiles can be estimated without keeping every sample. Hesitations make typing times very skewed, so the
+//! median says a lot more about how fast a transition really is than the mean does.
+//!
+//! Old samples can be made to count for less with `decay`, which scales down everything recorded so far. `DigramTimingHint`
+//! does this as time passes (see `TimingSettings::half_life`), so a transition which isn't typed for a month fades as much as
+//! one which is.
+
+use std::{collections::BTreeMap, time::Duration};
+
+/// How much wider each bucket is than the one before it.
+const BUCKET_GROWTH: f64 = 1.05;
+/// The lower edge of bucket 0, in seconds. Anything faster goes in bucket 0 as well.
+const SMALLEST_BUCKET: f64 = 0.001;
+
+fn bucket_of(time: f64) -> u16 {
+    ((time / SMALLEST_BUCKET).ln() / BUCKET_GROWTH.ln()).max(0.0) as u16
+}
+
+/// The middle of a bucket (on the log scale), in seconds.
+fn bucket_middle(bucket: u16) -> f64 {
+    SMALLEST_BUCKET * BUCKET_GROWTH.powf(bucket as f64 + 0.5)
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TransitionTiming {
+    samples: usize,
+    /// The total weight of the samples. This is the same as `samples` unless there is a decay.
+    weight: f64,
+    /// In seconds.
+    mean: f64,
+    /// The weighted sum of the squared differences from the mean, which the variance is worked out from.
+    squared_deviations: f64,
+    /// The weight of the samples in each bucket.
+    histogram: BTreeMap<u16, f64>,
+}
+
+impl TransitionTiming {
+    /// Rebuilds a timing from the numbers saved in a statistics file. The variance is only as accurate as the standard deviation
+    /// was when it was rounded.
+    pub fn from_summary(
+        mean: Duration,
+        standard_deviation: Duration,
+        samples: usize,
+        weight: f64,
+        histogram: BTreeMap<u16, f64>,
+    ) -> Self {
+        Self {
+            samples,
+            weight,
+            mean: mean.as_secs_f64(),
+            squared_deviations: standard_deviation.as_secs_f64().powi(2) * weight,
+            histogram,
+        }
+    }
+
+    /// Scales down the weight of the samples so far to `kept` (between 0 and 1) of what it was. On its own this doesn't change
+    /// any of the times, only how much the samples added after it count for next to the older ones.
+    pub fn decay(&mut self, kept: f64) {
+        self.weight *= kept;
+        self.squared_deviations *= kept;
+        for weight in self.histogram.values_mut() {
+            *weight *= kept;
+        }
+    }
+
+    /// Adds a sample with a weight of 1.
+    pub fn add_sample(&mut self, time: Duration) {
+        let time = time.as_secs_f64();
+        self.samples += 1;
+        self.weight += 1.0;
+        let deviation = time - self.mean;
+        self.mean += deviation / self.weight;
+        self.squared_deviations += deviation * (time - self.mean);
+        *self.histogram.entry(bucket_of(time)).or_insert(0.0) += 1.0;
+    }
+
+    /// Combines the samples of two timings, as if they had all been added to one.
+    pub fn merge(&mut self, other: &TransitionTiming) {
+        let weight = self.weight + other.weight;
+        if weight == 0.0 {
+            return;
+        }
+        let deviation = other.mean - self.mean;
+        self.squared_deviations +=
+            other.squared_deviations + deviation.powi(2) * self.weight * other.weight / weight;
+        self.mean += deviation * other.weight / weight;
+        self.weight = weight;
+        self.samples += other.samples;
+        for (&bucket, &other_weight) in &other.histogram {
+            *self.histogram.entry(bucket).or_insert(0.0) += other_weight;
+        }
+    }
+
+    /// How many samples have been added, however much they have decayed since.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// The weight of the samples in each bucket, for saving.
+    pub fn histogram(&self) -> &BTreeMap<u16, f64> {
+        &self.histogram
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::from_secs_f64(self.mean)
+    }
+
+    /// The population variance, in seconds squared.
+    pub fn variance(&self) -> f64 {
+        if self.weight == 0.0 {
+            0.0
+        } else {
+            self.squared_deviations / self.weight
+        }
+    }
+
+    pub fn standard_deviation(&self) -> Duration {
+        Duration::from_secs_f64(self.variance().sqrt())
+    }
+
+    /// Estimates the time that `fraction` (from 0 to 1) of the samples are faster than. It is accurate to within a few percent.
+    ///
+    /// Timings loaded from an old statistics file don't have the buckets, so this falls back to the mean for them.
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        let total: f64 = self.histogram.values().sum();
+        if total == 0.0 {
+            return self.mean();
+        }
+        let target = fraction.clamp(0.0, 1.0) * total;
+        let mut seen = 0.0;
+        for (&bucket, &weight) in &self.histogram {
+            seen += weight;
+            if seen >= target {
+                return Duration::from_secs_f64(bucket_middle(bucket));
+            }
+        }
+        Duration::from_secs_f64(bucket_middle(*self.histogram.keys().last().unwrap()))
+    }
+
+    pub fn median(&self) -> Duration {
+        self.percentile(0.5)
+    }
+
+    /// Whether `time` is more than `factor` times slower (or faster) than the median.
+    pub fn is_outlier(&self, time: Duration, factor: f64) -> bool {
+        let median = self.median().as_secs_f64();
+        let time = time.as_secs_f64();
+        time > median * factor || time * factor < median
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(milliseconds: &[u64]) -> TransitionTiming {
+        let mut timing = TransitionTiming::default();
+        for &milliseconds in milliseconds {
+            timing.add_sample(Duration::from_millis(milliseconds));
+        }
+        timing
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "{value} isn't close to {expected}"
+        );
+    }
+
+    #[test]
+    fn the_mean_and_variance_are_of_all_samples() {
+        let timing = timing(&[100, 200, 300, 400]);
+        assert_eq!(timing.samples(), 4);
+        assert_close(timing.weight(), 4.0);
+        assert_close(timing.mean().as_secs_f64(), 0.25);
+        assert_close(timing.variance(), 0.0125);
+    }
+
+    #[test]
+    fn percentiles_are_within_a_bucket() {
+        let timing = timing(&(1..=100).map(|index| index * 10).collect::<Vec<_>>());
+        for (fraction, expected) in [(0.1, 0.1), (0.5, 0.5), (0.9, 0.9)] {
+            let percentile = timing.percentile(fraction).as_secs_f64();
+            assert!(
+                (percentile / expected - 1.0).abs() < BUCKET_GROWTH - 1.0,
+                "{percentile} isn't close to {expected}"
+            );
+        }
+        assert_eq!(timing.median(), timing.percentile(0.5));
+        // Without buckets, the mean is all there is.
+        let summary = TransitionTiming::from_summary(
+            Duration::from_millis(120),
+            Duration::ZERO,
+            5,
+            5.0,
+            BTreeMap::new(),
+        );
+        assert_eq!(summary.median(), Duration::from_millis(120));
+    }
+
+    #[test]
+    fn decayed_samples_count_for_less() {
+        let mut timing = timing(&[100, 100]);
+        timing.decay(0.5);
+        assert_close(timing.weight(), 1.0);
+        assert_close(timing.mean().as_secs_f64(), 0.1);
+        timing.add_sample(Duration::from_millis(400));
+        // The two old samples weigh as much as the new one together.
+        assert_eq!(timing.samples(), 3);
+        assert_close(timing.weight(), 2.0);
+        assert_close(timing.mean().as_secs_f64(), 0.25);
+        assert_close(timing.variance(), 0.0225);
+        assert_close(timing.histogram().values().sum(), 2.0);
+    }
+
+    #[test]
+    fn merging_is_the_same_as_adding_every_sample() {
+        let mut merged = timing(&[100, 150]);
+        merged.merge(&timing(&[300, 500, 700]));
+        let all = timing(&[100, 150, 300, 500, 700]);
+        assert_eq!(merged.samples(), all.samples());
+        assert_close(merged.mean().as_secs_f64(), all.mean().as_secs_f64());
+        assert_close(merged.variance(), all.variance());
+        assert_eq!(merged.histogram(), all.histogram());
+    }
+
+    #[test]
+    fn outliers_are_far_from_the_median() {
+        let timing = timing(&[100, 110, 120]);
+        assert!(!timing.is_outlier(Duration::from_millis(250), 3.0));
+        assert!(timing.is_outlier(Duration::from_millis(400), 3.0));
+        assert!(timing.is_outlier(Duration::from_millis(30), 3.0));
+    }
+}
@@ -0,0 +1,200 @@
+//! Set 1 scancodes, which Windows reports for each physical key whatever the system layout, and their mapping to `KeyCode`s.
+//!
+//! Keys that send an 0xE0 prefix, like right Control, share their scancode with another key, so they are stored with 0xE000 added.
+
+use crate::keyboard::KeyCode;
+
+/// Marks a scancode as one of the extended keys.
+pub const EXTENDED: u16 = 0xE000;
+
+pub const KEY_Q: u16 = 0x10;
+pub const KEY_W: u16 = 0x11;
+pub const KEY_E: u16 = 0x12;
+pub const KEY_R: u16 = 0x13;
+pub const KEY_T: u16 = 0x14;
+pub const KEY_Y: u16 = 0x15;
+pub const KEY_U: u16 = 0x16;
+pub const KEY_I: u16 = 0x17;
+pub const KEY_O: u16 = 0x18;
+pub const KEY_P: u16 = 0x19;
+pub const KEY_A: u16 = 0x1E;
+pub const KEY_S: u16 = 0x1F;
+pub const KEY_D: u16 = 0x20;
+pub const KEY_F: u16 = 0x21;
+pub const KEY_G: u16 = 0x22;
+pub const KEY_H: u16 = 0x23;
+pub const KEY_J: u16 = 0x24;
+pub const KEY_K: u16 = 0x25;
+pub const KEY_L: u16 = 0x26;
+pub const KEY_SEMICOLON: u16 = 0x27;
+pub const KEY_Z: u16 = 0x2C;
+pub const KEY_X: u16 = 0x2D;
+pub const KEY_C: u16 = 0x2E;
+pub const KEY_V: u16 = 0x2F;
+pub const KEY_B: u16 = 0x30;
+pub const KEY_N: u16 = 0x31;
+pub const KEY_M: u16 = 0x32;
+pub const KEY_COMMA: u16 = 0x33;
+pub const KEY_DOT: u16 = 0x34;
+pub const KEY_SLASH: u16 = 0x35;
+pub const KEY_1: u16 = 0x02;
+pub const KEY_2: u16 = 0x03;
+pub const KEY_3: u16 = 0x04;
+pub const KEY_4: u16 = 0x05;
+pub const KEY_5: u16 = 0x06;
+pub const KEY_6: u16 = 0x07;
+pub const KEY_7: u16 = 0x08;
+pub const KEY_8: u16 = 0x09;
+pub const KEY_9: u16 = 0x0A;
+pub const KEY_0: u16 = 0x0B;
+pub const KEY_MINUS: u16 = 0x0C;
+pub const KEY_EQUAL: u16 = 0x0D;
+pub const KEY_BACKSPACE: u16 = 0x0E;
+pub const KEY_TAB: u16 = 0x0F;
+pub const KEY_LEFTBRACE: u16 = 0x1A;
+pub const KEY_RIGHTBRACE: u16 = 0x1B;
+pub const KEY_ENTER: u16 = 0x1C;
+pub const KEY_LEFTCTRL: u16 = 0x1D;
+pub const KEY_APOSTROPHE: u16 = 0x28;
+pub const KEY_GRAVE: u16 = 0x29;
+pub const KEY_LEFTSHIFT: u16 = 0x2A;
+pub const KEY_BACKSLASH: u16 = 0x2B;
+pub const KEY_RIGHTSHIFT: u16 = 0x36;
+pub const KEY_LEFTALT: u16 = 0x38;
+pub const KEY_SPACE: u16 = 0x39;
+pub const KEY_CAPSLOCK: u16 = 0x3A;
+pub const KEY_RIGHTCTRL: u16 = 0xE01D;
+pub const KEY_RIGHTALT: u16 = 0xE038;
+pub const KEY_LEFTMETA: u16 = 0xE05B;
+pub const KEY_RIGHTMETA: u16 = 0xE05C;
+
+pub fn key_code_from_scancode(scancode: u16) -> Option<KeyCode> {
+    match scancode {
+        KEY_Q => Some(KeyCode::Q),
+        KEY_W => Some(KeyCode::W),
+        KEY_E => Some(KeyCode::E),
+        KEY_R => Some(KeyCode::R),
+        KEY_T => Some(KeyCode::T),
+        KEY_Y => Some(KeyCode::Y),
+        KEY_U => Some(KeyCode::U),
+        KEY_I => Some(KeyCode::I),
+        KEY_O => Some(KeyCode::O),
+        KEY_P => Some(KeyCode::P),
+        KEY_A => Some(KeyCode::A),
+        KEY_S => Some(KeyCode::S),
+        KEY_D => Some(KeyCode::D),
+        KEY_F => Some(KeyCode::F),
+        KEY_G => Some(KeyCode::G),
+        KEY_H => Some(KeyCode::H),
+        KEY_J => Some(KeyCode::J),
+        KEY_K => Some(KeyCode::K),
+        KEY_L => Some(KeyCode::L),
+        KEY_SEMICOLON => Some(KeyCode::Semicolon),
+        KEY_Z => Some(KeyCode::Z),
+        KEY_X => Some(KeyCode::X),
+        KEY_C => Some(KeyCode::C),
+        KEY_V => Some(KeyCode::V),
+        KEY_B => Some(KeyCode::B),
+        KEY_N => Some(KeyCode::N),
+        KEY_M => Some(KeyCode::M),
+        KEY_COMMA => Some(KeyCode::Comma),
+        KEY_DOT => Some(KeyCode::Dot),
+        KEY_SLASH => Some(KeyCode::Slash),
+        KEY_1 => Some(KeyCode::Digit1),
+        KEY_2 => Some(KeyCode::Digit2),
+        KEY_3 => Some(KeyCode::Digit3),
+        KEY_4 => Some(KeyCode::Digit4),
+        KEY_5 => Some(KeyCode::Digit5),
+        KEY_6 => Some(KeyCode::Digit6),
+        KEY_7 => Some(KeyCode::Digit7),
+        KEY_8 => Some(KeyCode::Digit8),
+        KEY_9 => Some(KeyCode::Digit9),
+        KEY_0 => Some(KeyCode::Digit0),
+        KEY_MINUS => Some(KeyCode::Minus),
+        KEY_EQUAL => Some(KeyCode::Equal),
+        KEY_BACKSPACE => Some(KeyCode::Backspace),
+        KEY_TAB => Some(KeyCode::Tab),
+        KEY_LEFTBRACE => Some(KeyCode::LeftBracket),
+        KEY_RIGHTBRACE => Some(KeyCode::RightBracket),
+        KEY_ENTER => Some(KeyCode::Enter),
+        KEY_LEFTCTRL => Some(KeyCode::LeftControl),
+        KEY_APOSTROPHE => Some(KeyCode::Apostrophe),
+        KEY_GRAVE => Some(KeyCode::Grave),
+        KEY_LEFTSHIFT => Some(KeyCode::LeftShift),
+        KEY_BACKSLASH => Some(KeyCode::Backslash),
+        KEY_RIGHTSHIFT => Some(KeyCode::RightShift),
+        KEY_LEFTALT => Some(KeyCode::LeftAlt),
+        KEY_SPACE => Some(KeyCode::Space),
+        KEY_CAPSLOCK => Some(KeyCode::CapsLock),
+        KEY_RIGHTCTRL => Some(KeyCode::RightControl),
+        KEY_RIGHTALT => Some(KeyCode::RightAlt),
+        KEY_LEFTMETA => Some(KeyCode::LeftMeta),
+        KEY_RIGHTMETA => Some(KeyCode::RightMeta),
+        _ => None,
+    }
+}
+
+pub fn scancode_of(key_code: KeyCode) -> u16 {
+    match key_code {
+        KeyCode::Q => KEY_Q,
+        KeyCode::W => KEY_W,
+        KeyCode::E => KEY_E,
+        KeyCode::R => KEY_R,
+        KeyCode::T => KEY_T,
+        KeyCode::Y => KEY_Y,
+        KeyCode::U => KEY_U,
+        KeyCode::I => KEY_I,
+        KeyCode::O => KEY_O,
+        KeyCode::P => KEY_P,
+        KeyCode::A => KEY_A,
+        KeyCode::S => KEY_S,
+        KeyCode::D => KEY_D,
+        KeyCode::F => KEY_F,
+        KeyCode::G => KEY_G,
+        KeyCode::H => KEY_H,
+        KeyCode::J => KEY_J,
+        KeyCode::K => KEY_K,
+        KeyCode::L => KEY_L,
+        KeyCode::Semicolon => KEY_SEMICOLON,
+        KeyCode::Z => KEY_Z,
+        KeyCode::X => KEY_X,
+        KeyCode::C => KEY_C,
+        KeyCode::V => KEY_V,
+        KeyCode::B => KEY_B,
+        KeyCode::N => KEY_N,
+        KeyCode::M => KEY_M,
+        KeyCode::Comma => KEY_COMMA,
+        KeyCode::Dot => KEY_DOT,
+        KeyCode::Slash => KEY_SLASH,
+        KeyCode::Digit1 => KEY_1,
+        KeyCode::Digit2 => KEY_2,
+        KeyCode::Digit3 => KEY_3,
+        KeyCode::Digit4 => KEY_4,
+        KeyCode::Digit5 => KEY_5,
+        KeyCode::Digit6 => KEY_6,
+        KeyCode::Digit7 => KEY_7,
+        KeyCode::Digit8 => KEY_8,
+        KeyCode::Digit9 => KEY_9,
+        KeyCode::Digit0 => KEY_0,
+        KeyCode::Minus => KEY_MINUS,
+        KeyCode::Equal => KEY_EQUAL,
+        KeyCode::Backspace => KEY_BACKSPACE,
+        KeyCode::Tab => KEY_TAB,
+        KeyCode::LeftBracket => KEY_LEFTBRACE,
+        KeyCode::RightBracket => KEY_RIGHTBRACE,
+        KeyCode::Enter => KEY_ENTER,
+        KeyCode::LeftControl => KEY_LEFTCTRL,
+        KeyCode::Apostrophe => KEY_APOSTROPHE,
+        KeyCode::Grave => KEY_GRAVE,
+        KeyCode::LeftShift => KEY_LEFTSHIFT,
+        KeyCode::Backslash => KEY_BACKSLASH,
+        KeyCode::RightShift => KEY_RIGHTSHIFT,
+        KeyCode::LeftAlt => KEY_LEFTALT,
+        KeyCode::Space => KEY_SPACE,
+        KeyCode::CapsLock => KEY_CAPSLOCK,
+        KeyCode::RightControl => KEY_RIGHTCTRL,
+        KeyCode::RightAlt => KEY_RIGHTALT,
+        KeyCode::LeftMeta => KEY_LEFTMETA,
+        KeyCode::RightMeta => KEY_RIGHTMETA,
+    }
+}
@@ -1,73 +1,56 @@
-use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
-    Mutex,
-};
+use std::{collections::BTreeSet, error::Error, sync::Mutex};
 
 use windows::Win32::{
     Foundation::{HINSTANCE, LPARAM, LRESULT, WPARAM},
-    UI::{
-        Input::KeyboardAndMouse::{
-            VIRTUAL_KEY, VK_A, VK_B, VK_C, VK_D, VK_E, VK_F, VK_G, VK_H, VK_I, VK_J, VK_K, VK_L,
-            VK_M, VK_N, VK_O, VK_OEM_1, VK_OEM_2, VK_OEM_COMMA, VK_OEM_PERIOD, VK_P, VK_Q, VK_R,
-            VK_S, VK_T, VK_U, VK_V, VK_W, VK_X, VK_Y, VK_Z,
-        },
-        WindowsAndMessaging::{
-            CallNextHookEx, SetWindowsHookExA, UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT,
-            WH_KEYBOARD_LL, WM_KEYDOWN, WM_SYSKEYDOWN,
-        },
+    UI::WindowsAndMessaging::{
+        CallNextHookEx, DispatchMessageA, GetMessageA, SetWindowsHookExA, UnhookWindowsHookEx,
+        HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, MSG, WH_KEYBOARD_LL, WM_KEYDOWN, WM_KEYUP,
+        WM_SYSKEYDOWN, WM_SYSKEYUP,
     },
 };
 
-use crate::{input::generate_key_stroke, keyboard::KeyCode};
+use crate::{
+    input::{generate_key_event, generate_key_stroke},
+    key_event_source::{KeyEventCallback, KeyEventSource, Subscribers, SubscriptionId},
+    keyboard::KeyCode,
+    scancode::{key_code_from_scancode, EXTENDED},
+    session::KeyEventKind,
+};
 
-static HOOK_HANDLE: Mutex<Option<HHOOK>> = Mutex::new(None);
+struct ActiveHook {
+    handle: HHOOK,
+    subscribers: Subscribers,
+    held_keys: BTreeSet<KeyCode>,
+}
 
-fn install_keyboard_hook() {
-    let mut hook_handle = HOOK_HANDLE.lock().unwrap();
-    *hook_handle = Some(unsafe {
+impl ActiveHook {
+    fn send_key_events(&mut self, events: &[(KeyCode, KeyEventKind)]) -> windows::core::Result<()> {
+        // We don't want to receive the keystroke event ourselves. This is apparently how to get around this:
+        unsafe {
+            UnhookWindowsHookEx(self.handle)?;
+        }
+        for &(key_code, kind) in events {
+            generate_key_event(key_code, kind);
+        }
+        self.handle = install_keyboard_hook()?;
+        Ok(())
+    }
+}
+
+// The hook callback doesn't get any state of its own, so the running tracer has to be reachable from here.
+static ACTIVE_HOOK: Mutex<Option<ActiveHook>> = Mutex::new(None);
+
+fn install_keyboard_hook() -> windows::core::Result<HHOOK> {
+    unsafe {
         SetWindowsHookExA(
             WH_KEYBOARD_LL,
             Some(keyboard_hook_callback),
             HINSTANCE::default(),
             0,
         )
-        .unwrap()
-    });
-}
-
-fn uninstall_keyboard_hook() {
-    let mut hook_handle = HOOK_HANDLE.lock().unwrap();
-    unsafe {
-        UnhookWindowsHookEx(hook_handle.expect("Uninstalling hook before installing")).unwrap();
     }
-    *hook_handle = None;
 }
 
-#[derive(Default)]
-pub struct Context {
-    suppress: bool,
-    sent_keystrokes: Vec<KeyCode>,
-}
-
-impl Context {
-    pub fn suppress(&mut self) {
-        self.suppress = true;
-    }
-
-    pub fn send_keystroke(&mut self, key_code: KeyCode) {
-        self.sent_keystrokes.push(key_code);
-    }
-}
-
-type BoxedCallbackFunction = Box<dyn FnMut(&mut Context, KeyCode) + Send>;
-
-struct CallbackEntry {
-    callback: BoxedCallbackFunction,
-    id: usize,
-}
-
-static CALLBACK_HANDLERS: Mutex<Option<Vec<CallbackEntry>>> = Mutex::new(None);
-
 unsafe extern "system" fn keyboard_hook_callback(
     code: i32,
     wparam: WPARAM,
@@ -75,60 +58,40 @@ unsafe extern "system" fn keyboard_hook_callback(
 ) -> LRESULT {
     if code >= 0 {
         let event_type = wparam.0 as u32;
-        if event_type == WM_KEYDOWN || event_type == WM_SYSKEYDOWN {
-            let event_info = &mut *(lparam.0 as *mut KBDLLHOOKSTRUCT);
-            if let Some(key_code) = match VIRTUAL_KEY(event_info.vkCode as u16) {
-                VK_Q => Some(KeyCode::Q),
-                VK_W => Some(KeyCode::W),
-                VK_E => Some(KeyCode::E),
-                VK_R => Some(KeyCode::R),
-                VK_T => Some(KeyCode::T),
-                VK_Y => Some(KeyCode::Y),
-                VK_U => Some(KeyCode::U),
-                VK_I => Some(KeyCode::I),
-                VK_O => Some(KeyCode::O),
-                VK_P => Some(KeyCode::P),
-                VK_A => Some(KeyCode::A),
-                VK_S => Some(KeyCode::S),
-                VK_D => Some(KeyCode::D),
-                VK_F => Some(KeyCode::F),
-                VK_G => Some(KeyCode::G),
-                VK_H => Some(KeyCode::H),
-                VK_J => Some(KeyCode::J),
-                VK_K => Some(KeyCode::K),
-                VK_L => Some(KeyCode::L),
-                // On standard US QWERTY, the ';' key is mapped to VK_OEM_1 for some reason.
-                VK_OEM_1 => Some(KeyCode::Semicolon),
-                VK_Z => Some(KeyCode::Z),
-                VK_X => Some(KeyCode::X),
-                VK_C => Some(KeyCode::C),
-                VK_V => Some(KeyCode::V),
-                VK_B => Some(KeyCode::B),
-                VK_N => Some(KeyCode::N),
-                VK_M => Some(KeyCode::M),
-                VK_OEM_COMMA => Some(KeyCode::Comma),
-                VK_OEM_PERIOD => Some(KeyCode::Dot),
-                // '/' is also OEM_2.
-                VK_OEM_2 => Some(KeyCode::Slash),
-                _ => None,
-            } {
-                let mut callbacks = CALLBACK_HANDLERS.lock().unwrap();
-                if let Some(callbacks) = &mut *callbacks {
-                    for callback in callbacks {
-                        let mut context = Context::default();
-                        (callback.callback)(&mut context, key_code);
-                        if context.sent_keystrokes.len() > 0 {
-                            // We don't want to receive the keystroke event ourselves. This is apparently how to get around this:
-                            uninstall_keyboard_hook();
-                            for key_code in context.sent_keystrokes {
-                                generate_key_stroke(key_code);
-                            }
-                            install_keyboard_hook();
-                        }
-                        if context.suppress {
-                            return LRESULT(1);
+        let event_info = &mut *(lparam.0 as *mut KBDLLHOOKSTRUCT);
+        // Virtual keys depend on the system layout, so the physical key is taken from the scancode instead.
+        let scancode = if event_info.flags.contains(LLKHF_EXTENDED) {
+            event_info.scanCode as u16 | EXTENDED
+        } else {
+            event_info.scanCode as u16
+        };
+        if let Some(key_code) = key_code_from_scancode(scancode) {
+            let mut active_hook = ACTIVE_HOOK.lock().unwrap();
+            if let Some(active_hook) = &mut *active_hook {
+                if event_type == WM_KEYDOWN || event_type == WM_SYSKEYDOWN {
+                    active_hook.held_keys.insert(key_code);
+                    let context = active_hook.subscribers.dispatch(
+                        key_code,
+                        KeyEventKind::Down,
+                        &active_hook.held_keys,
+                    );
+                    if !context.sent_keystrokes().is_empty() {
+                        if let Err(error) = active_hook.send_key_events(&context.key_events()) {
+                            // Panicking here would abort the whole process, so the key press goes through as it is instead.
+                            eprintln!("Failed to send keystrokes: {error}");
+                            return CallNextHookEx(None, code, wparam, lparam);
                         }
                     }
+                    if context.is_suppressed() {
+                        return LRESULT(1);
+                    }
+                } else if event_type == WM_KEYUP || event_type == WM_SYSKEYUP {
+                    active_hook.held_keys.remove(&key_code);
+                    active_hook.subscribers.dispatch(
+                        key_code,
+                        KeyEventKind::Up,
+                        &active_hook.held_keys,
+                    );
                 }
             }
         }
@@ -136,40 +99,83 @@ unsafe extern "system" fn keyboard_hook_callback(
     CallNextHookEx(None, code, wparam, lparam)
 }
 
-pub struct Tracer(usize);
+/// Handles window messages until the process is ended.
+///
+/// The hook is only called while its thread is handling messages, which the GUI normally does for us.
+pub fn run_event_loop() {
+    let mut message = MSG::default();
+    unsafe {
+        while GetMessageA(&mut message, None, 0, 0).as_bool() {
+            DispatchMessageA(&message);
+        }
+    }
+}
+
+/// Receives key presses through a low level keyboard hook.
+///
+/// Windows only delivers hook events to threads with a message loop, so this has to be started on the GUI thread or one running `run_event_loop`.
+#[derive(Default)]
+pub struct Tracer {
+    subscribers: Subscribers,
+}
 
 impl Tracer {
-    pub fn new<CallbackFunction>(callback: CallbackFunction) -> Self
-    where
-        CallbackFunction: FnMut(&mut Context, KeyCode) + Send + 'static,
-    {
-        static ADDED_HOOK: AtomicBool = AtomicBool::new(false);
-        // If we haven't already registered the global key hook, do it here.
-        if ADDED_HOOK
-            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
-        {
-            install_keyboard_hook();
+    fn is_active(&self, active_hook: &Option<ActiveHook>) -> bool {
+        active_hook
+            .as_ref()
+            .is_some_and(|active_hook| active_hook.subscribers.same_as(&self.subscribers))
+    }
+}
+
+impl KeyEventSource for Tracer {
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut active_hook = ACTIVE_HOOK.lock().unwrap();
+        if self.is_active(&active_hook) {
+            return Ok(());
         }
-        let mut handlers = CALLBACK_HANDLERS.lock().unwrap();
-        if handlers.is_none() {
-            *handlers = Some(Vec::new());
+        if active_hook.is_some() {
+            return Err("Only one tracer can be running at a time".into());
         }
-        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
-        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
-        handlers.as_mut().unwrap().push(CallbackEntry {
-            callback: Box::new(callback),
-            id,
+        *active_hook = Some(ActiveHook {
+            handle: install_keyboard_hook()?,
+            subscribers: self.subscribers.clone(),
+            held_keys: BTreeSet::new(),
         });
-        Self(id)
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        let mut active_hook = ACTIVE_HOOK.lock().unwrap();
+        if self.is_active(&active_hook) {
+            let handle = active_hook.take().unwrap().handle;
+            // This also runs when dropping during a panic, so failing here mustn't panic again.
+            if let Err(error) = unsafe { UnhookWindowsHookEx(handle) } {
+                eprintln!("Failed to remove the keyboard hook: {error}");
+            }
+        }
+    }
+
+    fn subscribe(&mut self, callback: KeyEventCallback) -> SubscriptionId {
+        self.subscribers.subscribe(callback)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscribers.unsubscribe(id);
+    }
+
+    fn inject(&mut self, key_code: KeyCode) -> Result<(), Box<dyn Error>> {
+        let mut active_hook = ACTIVE_HOOK.lock().unwrap();
+        match &mut *active_hook {
+            Some(active_hook) if active_hook.subscribers.same_as(&self.subscribers) => active_hook
+                .send_key_events(&[(key_code, KeyEventKind::Down), (key_code, KeyEventKind::Up)])?,
+            _ => generate_key_stroke(key_code),
+        }
+        Ok(())
     }
 }
 
 impl Drop for Tracer {
     fn drop(&mut self) {
-        let mut handlers = CALLBACK_HANDLERS.lock().unwrap();
-        if let Some(handlers) = &mut *handlers {
-            handlers.retain(|entry| entry.id != self.0);
-        }
+        self.stop();
     }
 }